@@ -10,6 +10,7 @@ impl fmt::Display for DebugEvent {
             DebugEvent::InstructionEnd(ir) => format!("InstructionEnd({ir:#02X})"),
             DebugEvent::Register16Change(reg, value) => format!("RegChange({value:#04X} => {reg})"),
             DebugEvent::Register8Change(reg, value) => format!("RegChange({value:#02X} => {reg})"),
+            DebugEvent::PpuModeChange(mode) => format!("PpuModeChange({mode:?})"),
         };
 
         write!(f, "{}", s)
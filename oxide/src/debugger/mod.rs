@@ -33,6 +33,7 @@ pub enum DebugEvent {
     IrPrefetch(u8, u16),
     Register8Change(Reg8, u8),
     Register16Change(Reg16, u16),
+    PpuModeChange(Mode),
 }
 
 
@@ -110,7 +110,9 @@ pub fn tui_main(emu: Emulator) -> Result<(), String> {
     let dbg =  FullDebugger::new(emu.cpu.pc);
     let mut ui = Ui::new(emu, dbg);
 
-    if let Ok(_) = ui.run() {
+    let res = ui.run();
+    ui.emulator.save();
+    if res.is_ok() {
         Ok(())
     } else {
         Err("Run error".to_string())
@@ -3,6 +3,7 @@ use crate::debugger::full_debugger::*;
 use super::ui_utils::*;
 use super::*;
 use log::debug;
+use std::path::Path;
 use log::Level::{Error, Info, Trace, Warn};
 
 impl<'a> Ui<'a> {
@@ -27,6 +28,12 @@ impl<'a> Ui<'a> {
                 "continue" | "c" => self.tick(),
                 "mem" | "m" => self.parse_mem(&words[1..]),
                 "log" => self.parse_log(&words[1..]),
+                "header" => {
+                    for line in self.emulator.bus.cartridge.header().to_string().lines() {
+                        info!("{line}");
+                    }
+                },
+                "record" => self.parse_record(&words[1..]),
                 "cycle" => {
                     let cycles = self.emulator.get_t_cycle();
                     info!("Current T-Cycle: {cycles}")
@@ -57,6 +64,26 @@ impl<'a> Ui<'a> {
         }
     }
     
+    fn parse_record(&mut self, words: &[&str]) {
+        let res = match words {
+            [] | ["stop"] => {
+                if !self.emulator.apu.is_recording() {
+                    info!("No audio recording in progress");
+                }
+                self.emulator.apu.stop_recording()
+            },
+            [path] => self.emulator.apu.start_recording(Path::new(path), false),
+            [path, "channels"] => self.emulator.apu.start_recording(Path::new(path), true),
+            _ => {
+                self.cmd_area.insert_str("Error: Usage: record file.wav [channels] | record stop");
+                return;
+            }
+        };
+        if let Err(e) = res {
+            error!("{e}");
+        }
+    }
+
     fn parse_breakpoint(&mut self, words: &[&str]) -> bool {
         if words.len() < 1 || words.len() > 3 {
             self.cmd_area.insert_str("Error: Invalid breakpoint argument count !\nUsage: break type value");
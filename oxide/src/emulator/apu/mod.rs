@@ -0,0 +1,289 @@
+mod units;
+mod square;
+mod wave;
+mod noise;
+pub mod wav;
+mod state;
+
+use noise::Noise;
+use square::Square;
+use wave::Wave;
+use wav::Recorder;
+
+use crate::emulator::memory::Bus;
+use crate::emulator::memory::regdefines::*;
+use log::{debug, error, info};
+use std::path::Path;
+
+/*
+ * Audio Processing Unit.
+ * Register writes are forwarded by the bus and applied on the next tick.
+ * The frame sequencer is clocked at 512Hz by the falling edge of DIV bit 4
+ * (bit 5 in CGB double speed mode):
+ *   - Length counters on even steps
+ *   - CH1 sweep on steps 2 and 6
+ *   - Volume envelopes on step 7
+ * Channels are mixed according to NR51 (panning) and NR50 (master volume),
+ * then averaged down to the output sample rate and high-pass filtered like
+ * the output capacitors of the console.
+ */
+
+pub type AudioChunk = Vec<f32>; // Interleaved stereo samples
+
+pub const CPU_FREQ: u32 = 4_194_304;
+
+// Samples sent to the frontend at once, about 10ms at 48kHz
+const CHUNK_SAMPLES: usize = 1024;
+
+// Unused bits of the sound registers (0xFF10-0xFF2F) read as 1
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70,             // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+pub fn read_mask(addr: u16) -> u8 {
+    READ_MASKS[(addr - NR10) as usize]
+}
+
+pub struct Apu {
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+
+    powered: bool,
+    fs_step: u8,        // Next frame sequencer step
+    last_div_bit: bool,
+
+    sample_rate: u32,
+    cycles_per_sample: f64,
+    sample_timer: f64,
+    acc: [f32; 2],
+    channel_acc: [f32; 4], // Unmixed channels, for recordings
+    acc_count: u32,
+    hpf_charge: f32,
+    hpf_caps: [f32; 2],
+
+    samples: AudioChunk,
+    recorder: Option<Recorder>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        let cycles_per_sample = CPU_FREQ as f64 / sample_rate as f64;
+        Apu {
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+
+            powered: false,
+            fs_step: 0,
+            last_div_bit: false,
+
+            sample_rate,
+            cycles_per_sample,
+            sample_timer: 0.0,
+            acc: [0.0; 2],
+            channel_acc: [0.0; 4],
+            acc_count: 0,
+            hpf_charge: 0.999958f32.powf(cycles_per_sample as f32),
+            hpf_caps: [0.0; 2],
+
+            samples: Vec::with_capacity(CHUNK_SAMPLES),
+            recorder: None,
+        }
+    }
+
+    // Register state left by the boot ROM
+    pub fn init_noboot(&mut self, bus: &mut Bus) {
+        for (addr, value) in [(NR52, 0x80), (NR50, 0x77), (NR51, 0xF3), (NR11, 0x80), (NR12, 0xF3)] {
+            self.write(bus, addr, value);
+        }
+    }
+
+    // Should be ticked every T cycle, after the timer
+    pub fn tick(&mut self, bus: &mut Bus) {
+        if let Some((addr, value)) = bus.apu_write.take() {
+            self.write(bus, addr, value);
+        }
+
+        // DIV runs twice as fast in double speed mode
+        let div_mask = if bus.double_speed {0x20} else {0x10};
+        let div_bit = bus.ioregs[0x04] & div_mask != 0;
+        if self.last_div_bit && !div_bit && self.powered {
+            self.step_frame_sequencer();
+        }
+        self.last_div_bit = div_bit;
+
+        if self.powered {
+            let wave_ram = &bus.ioregs[0x30..0x40];
+            self.square1.tick();
+            self.square2.tick();
+            self.wave.tick(wave_ram);
+            self.noise.tick();
+        }
+
+        bus.ioregs[0x26] = self.status();
+        self.sample(bus.ioregs[0x24], bus.ioregs[0x25]);
+
+        if self.samples.len() >= CHUNK_SAMPLES {
+            let chunk = std::mem::replace(&mut self.samples, Vec::with_capacity(CHUNK_SAMPLES));
+            bus.io_manager.send_audio(chunk);
+        }
+    }
+
+    // Records the output to a WAV file, and each channel to its own file if split_channels is set
+    pub fn start_recording(&mut self, path: &Path, split_channels: bool) -> Result<(), String> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(path, self.sample_rate, split_channels)?);
+        info!("Recording audio to {}", path.display());
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<(), String> {
+        match self.recorder.take() {
+            Some(mut recorder) => {
+                info!("Audio recording stopped");
+                recorder.finish()
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        if self.fs_step & 1 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.fs_step == 2 || self.fs_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.fs_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.fs_step = (self.fs_step + 1) & 0b111;
+    }
+
+    fn write(&mut self, bus: &mut Bus, addr: u16, value: u8) {
+        if addr == NR52 {
+            self.set_power(bus, value & 0x80 != 0);
+            return;
+        }
+
+        // Only the length counters can be written while the APU is off
+        if !self.powered {
+            match addr {
+                NR11 => self.square1.write_length(value),
+                NR21 => self.square2.write_length(value),
+                NR31 => self.wave.write_length(value),
+                NR41 => self.noise.write_length(value),
+                _ => debug!("Ignored write to {addr:#06X} while the APU is off"),
+            }
+            return;
+        }
+
+        // Lengths are clocked on even steps
+        let extra_clock = self.fs_step & 1 == 1;
+        match addr {
+            NR10..=NR14 => self.square1.write(addr - NR10, value, extra_clock),
+            0xFF15..=NR24 => self.square2.write(addr - 0xFF15, value, extra_clock),
+            NR30..=NR34 => self.wave.write(addr - NR30, value, extra_clock),
+            0xFF1F..=NR44 => self.noise.write(addr - 0xFF1F, value, extra_clock),
+            _ => (),
+        }
+        bus.ioregs[(addr - 0xFF00) as usize] = value;
+    }
+
+    fn set_power(&mut self, bus: &mut Bus, on: bool) {
+        if on && !self.powered {
+            self.fs_step = 0;
+        } else if !on && self.powered {
+            self.square1.reset();
+            self.square2.reset();
+            self.wave.reset();
+            self.noise.reset();
+            bus.ioregs[0x10..0x26].fill(0);
+        }
+        self.powered = on;
+    }
+
+    fn status(&self) -> u8 {
+        (self.powered as u8) << 7
+            | (self.noise.enabled as u8) << 3
+            | (self.wave.enabled as u8) << 2
+            | (self.square2.enabled as u8) << 1
+            | self.square1.enabled as u8
+    }
+
+    // DAC outputs, -1.0 to 1.0. Disabled DACs output 0
+    fn channel_outputs(&self) -> [f32; 4] {
+        let dac = |enabled: bool, digital: u8| {
+            if enabled {digital as f32 / 7.5 - 1.0} else {0.0}
+        };
+        [
+            dac(self.square1.dac_enabled(), self.square1.output()),
+            dac(self.square2.dac_enabled(), self.square2.output()),
+            dac(self.wave.dac_enabled(), self.wave.output()),
+            dac(self.noise.dac_enabled(), self.noise.output()),
+        ]
+    }
+
+    fn sample(&mut self, nr50: u8, nr51: u8) {
+        if self.powered {
+            let outputs = self.channel_outputs();
+            for (i, out) in outputs.iter().enumerate() {
+                self.channel_acc[i] += out;
+                if nr51 & (0x10 << i) != 0 {
+                    self.acc[0] += out;
+                }
+                if nr51 & (0x01 << i) != 0 {
+                    self.acc[1] += out;
+                }
+            }
+        }
+        self.acc_count += 1;
+
+        self.sample_timer += 1.0;
+        if self.sample_timer < self.cycles_per_sample {
+            return;
+        }
+        self.sample_timer -= self.cycles_per_sample;
+
+        let volumes = [((nr50 >> 4) & 0b111) + 1, (nr50 & 0b111) + 1];
+        let mut frame = [0.0; 2];
+        for (side, volume) in volumes.iter().enumerate() {
+            let mixed = self.acc[side] / self.acc_count as f32 / 4.0 * *volume as f32 / 8.0;
+            frame[side] = mixed - self.hpf_caps[side];
+            self.hpf_caps[side] = mixed - frame[side] * self.hpf_charge;
+        }
+        self.samples.extend(frame);
+
+        if let Some(recorder) = &mut self.recorder {
+            let channels = self.channel_acc.map(|acc| acc / self.acc_count as f32);
+            if let Err(e) = recorder.write(frame, channels) {
+                error!("Audio recording stopped: {e}");
+                self.recorder = None;
+            }
+        }
+        self.acc = [0.0; 2];
+        self.channel_acc = [0.0; 4];
+        self.acc_count = 0;
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/apu.rs"]
+mod apu_tests;
@@ -0,0 +1,150 @@
+use super::units::*;
+use crate::emulator::state::*;
+
+/*
+ * Noise channel (CH4).
+ * A 15-bit LFSR (7-bit in width mode) is clocked every divisor << shift
+ * T-cycles. The output is high when bit 0 of the LFSR is clear.
+ */
+
+const DIVISORS: [u16; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Noise {
+    pub enabled: bool,
+    nrx2: u8,
+    shift: u8,
+    width_mode: bool,
+    divisor: u8,
+    timer: u32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl SaveState for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u8(self.nrx2);
+        w.u8(self.shift);
+        w.bool(self.width_mode);
+        w.u8(self.divisor);
+        w.u32(self.timer);
+        w.u16(self.lfsr);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.bool()?;
+        self.nrx2 = r.u8()?;
+        self.shift = r.u8()?;
+        self.width_mode = r.bool()?;
+        self.divisor = r.u8()?;
+        self.timer = r.u32()?;
+        self.lfsr = r.u16()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)
+    }
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            enabled: false,
+            nrx2: 0,
+            shift: 0,
+            width_mode: false,
+            divisor: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    // Power off resets everything but the length counter
+    pub fn reset(&mut self) {
+        *self = Noise { length: self.length, ..Noise::new() };
+    }
+
+    fn period(&self) -> u32 {
+        (DIVISORS[self.divisor as usize] as u32) << self.shift
+    }
+
+    // reg is the register offset from the unused NR40 (0xFF1F)
+    pub fn write(&mut self, reg: u16, value: u8, extra_clock: bool) {
+        match reg {
+            1 => self.length.load(value & 0x3F),
+            2 => {
+                self.nrx2 = value;
+                self.envelope.write(value);
+                if !dac_enabled(value) {
+                    self.enabled = false;
+                }
+            },
+            3 => {
+                self.shift = value >> 4;
+                self.width_mode = value & 0b1000 != 0;
+                self.divisor = value & 0b111;
+            },
+            4 => {
+                let trigger = value & 0x80 != 0;
+                if self.length.write_control(value & 0x40 != 0, trigger, extra_clock) {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.enabled = dac_enabled(self.nrx2);
+                    self.timer = self.period();
+                    self.lfsr = 0x7FFF;
+                    self.envelope.trigger();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // Length writes are still accepted while the APU is off
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    // Should be ticked every T cycle
+    pub fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period();
+        // Shifts 14 and 15 do not clock the LFSR
+        if self.shift >= 14 {
+            return;
+        }
+
+        let xor = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (xor << 14);
+        if self.width_mode {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (xor << 6);
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        dac_enabled(self.nrx2)
+    }
+
+    // Digital output, 0 to 15
+    pub fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 1 == 0 {self.envelope.volume} else {0}
+    }
+}
@@ -0,0 +1,242 @@
+use super::units::*;
+use crate::emulator::state::*;
+
+/*
+ * Square channels (CH1 and CH2).
+ * The duty step advances every (2048 - frequency) * 4 T-cycles.
+ * CH1 adds a frequency sweep unit clocked at 128Hz by the frame sequencer.
+ */
+
+const DUTY_TABLE: [u8; 4] = [
+    0b0000_0001, // 12.5%
+    0b1000_0001, // 25%
+    0b1000_0111, // 50%
+    0b0111_1110, // 75%
+];
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    shadow: u16,
+    enabled: bool,
+    negate_used: bool, // Clearing negate after a negated calculation disables the channel
+}
+
+impl Sweep {
+    fn timer_reload(&self) -> u8 {
+        if self.period == 0 {8} else {self.period}
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow >> self.shift;
+        if self.negate {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+impl SaveState for Sweep {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.period);
+        w.bool(self.negate);
+        w.u8(self.shift);
+        w.u8(self.timer);
+        w.u16(self.shadow);
+        w.bool(self.enabled);
+        w.bool(self.negate_used);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.period = r.u8()?;
+        self.negate = r.bool()?;
+        self.shift = r.u8()?;
+        self.timer = r.u8()?;
+        self.shadow = r.u16()?;
+        self.enabled = r.bool()?;
+        self.negate_used = r.bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Square {
+    pub enabled: bool,
+    has_sweep: bool,
+    nrx2: u8,
+    duty: u8,
+    duty_pos: u8,
+    frequency: u16,
+    timer: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Sweep,
+}
+
+impl SaveState for Square {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u8(self.nrx2);
+        w.u8(self.duty);
+        w.u8(self.duty_pos);
+        w.u16(self.frequency);
+        w.u16(self.timer);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        self.sweep.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.bool()?;
+        self.nrx2 = r.u8()?;
+        self.duty = r.u8()?;
+        self.duty_pos = r.u8()?;
+        self.frequency = r.u16()?;
+        self.timer = r.u16()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        self.sweep.load_state(r)
+    }
+}
+
+impl Square {
+    pub fn new(has_sweep: bool) -> Self {
+        Square {
+            enabled: false,
+            has_sweep,
+            nrx2: 0,
+            duty: 0,
+            duty_pos: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+        }
+    }
+
+    // Power off resets everything but the length counter
+    pub fn reset(&mut self) {
+        *self = Square { length: self.length, ..Square::new(self.has_sweep) };
+    }
+
+    // reg is the register offset from NRx0
+    pub fn write(&mut self, reg: u16, value: u8, extra_clock: bool) {
+        match reg {
+            0 if self.has_sweep => {
+                self.sweep.period = (value >> 4) & 0b111;
+                self.sweep.negate = value & 0b1000 != 0;
+                self.sweep.shift = value & 0b111;
+                if self.sweep.negate_used && !self.sweep.negate {
+                    self.enabled = false;
+                }
+            },
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            },
+            2 => {
+                self.nrx2 = value;
+                self.envelope.write(value);
+                if !dac_enabled(value) {
+                    self.enabled = false;
+                }
+            },
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8);
+                let trigger = value & 0x80 != 0;
+                if self.length.write_control(value & 0x40 != 0, trigger, extra_clock) {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // Length writes are still accepted while the APU is off
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = dac_enabled(self.nrx2);
+        self.timer = (2048 - self.frequency) * 4;
+        self.envelope.trigger();
+
+        if self.has_sweep {
+            self.sweep.shadow = self.frequency;
+            self.sweep.timer = self.sweep.timer_reload();
+            self.sweep.enabled = self.sweep.period != 0 || self.sweep.shift != 0;
+            self.sweep.negate_used = false;
+            if self.sweep.shift != 0 && self.sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    // Should be ticked every T cycle
+    pub fn tick(&mut self) {
+        if self.timer <= 1 {
+            self.timer = (2048 - self.frequency) * 4;
+            self.duty_pos = (self.duty_pos + 1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if self.sweep.timer > 1 {
+            self.sweep.timer -= 1;
+            return;
+        }
+
+        self.sweep.timer = self.sweep.timer_reload();
+        if !self.sweep.enabled || self.sweep.period == 0 {
+            return;
+        }
+
+        let frequency = self.sweep.calculate();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if self.sweep.shift != 0 {
+            self.sweep.shadow = frequency;
+            self.frequency = frequency;
+            // The new value is checked again for overflow, but not used
+            if self.sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        dac_enabled(self.nrx2)
+    }
+
+    // Digital output, 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let high = DUTY_TABLE[self.duty as usize] >> (7 - self.duty_pos) & 1 != 0;
+        if high {self.envelope.volume} else {0}
+    }
+}
@@ -0,0 +1,41 @@
+use super::*;
+use crate::emulator::state::*;
+
+// The sample rate and the filter charge factor depend on the settings, and
+// the samples not sent yet to the frontend are kept
+impl SaveState for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.square1.save_state(w);
+        self.square2.save_state(w);
+        self.wave.save_state(w);
+        self.noise.save_state(w);
+
+        w.bool(self.powered);
+        w.u8(self.fs_step);
+        w.bool(self.last_div_bit);
+
+        w.f64(self.sample_timer);
+        for &value in self.acc.iter().chain(&self.channel_acc).chain(&self.hpf_caps) {
+            w.f32(value);
+        }
+        w.u32(self.acc_count);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.square1.load_state(r)?;
+        self.square2.load_state(r)?;
+        self.wave.load_state(r)?;
+        self.noise.load_state(r)?;
+
+        self.powered = r.bool()?;
+        self.fs_step = r.u8()?;
+        self.last_div_bit = r.bool()?;
+
+        self.sample_timer = r.f64()?;
+        for value in self.acc.iter_mut().chain(&mut self.channel_acc).chain(&mut self.hpf_caps) {
+            *value = r.f32()?;
+        }
+        self.acc_count = r.u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::apu::*;
+    use crate::emulator::apu::units::*;
+
+    fn run_square(square: &mut Square, cycles: usize) -> Vec<u8> {
+        (0..cycles).map(|_| {square.tick(); square.output()}).collect()
+    }
+
+    #[test]
+    fn test_read_masks() {
+        assert_eq!(read_mask(NR10), 0x80, "NR10");
+        assert_eq!(read_mask(NR13), 0xFF, "Frequency is write only");
+        assert_eq!(read_mask(NR30), 0x7F, "NR30");
+        assert_eq!(read_mask(NR52), 0x70, "NR52");
+        assert_eq!(read_mask(0xFF2F), 0xFF, "Unused registers");
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut length = LengthCounter::new(64);
+        length.load(62);
+        assert!(!length.write_control(true, false, false), "Enabling without extra clock");
+        assert!(!length.clock(), "One step left");
+        assert!(length.clock(), "Channel disabled on zero");
+        assert!(!length.clock(), "Stays at zero");
+
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+        assert!(length.write_control(true, false, true), "Extra clock disables the channel");
+
+        let mut length = LengthCounter::new(256);
+        length.write_control(true, true, true);
+        for _ in 0..254 {
+            assert!(!length.clock());
+        }
+        assert!(length.clock(), "Trigger with extra clock reloads 255");
+    }
+
+    #[test]
+    fn test_envelope() {
+        let mut envelope = Envelope::default();
+        envelope.write(0xF1);
+        envelope.trigger();
+        assert_eq!(envelope.volume, 15, "Initial volume");
+        envelope.clock();
+        assert_eq!(envelope.volume, 14, "Decrease");
+
+        envelope.write(0xE9);
+        envelope.trigger();
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.volume, 15, "Increase stops at 15");
+    }
+
+    #[test]
+    fn test_square_duty() {
+        let mut square = Square::new(false);
+        square.write(1, 0b1000_0000, false); // 50% duty
+        square.write(2, 0xF0, false);
+        square.write(3, 0xFF, false);
+        square.write(4, 0x87, false); // Period of 4 T-cycles per step
+
+        let out = run_square(&mut square, 32);
+        let high = out.iter().filter(|v| **v == 15).count();
+        assert_eq!(high, 16, "Half of the duty cycle is high");
+        assert!(square.enabled, "Channel enabled");
+
+        square.write(2, 0x00, false);
+        assert!(!square.enabled, "DAC off disables the channel");
+    }
+
+    #[test]
+    fn test_sweep_overflow() {
+        let mut square = Square::new(true);
+        square.write(2, 0xF0, false);
+        square.write(3, 0xFF, false);
+        square.write(0, 0x11, false); // Period 1, shift 1
+        square.write(4, 0x84, false); // Frequency 0x4FF
+        assert!(square.enabled, "0x4FF + 0x27F does not overflow");
+
+        square.clock_sweep();
+        assert!(!square.enabled, "0x77E + 0x3BF overflows on the second check");
+
+        square.write(3, 0x00, false);
+        square.write(4, 0x87, false); // Frequency 0x700
+        assert!(!square.enabled, "Overflow on trigger");
+    }
+
+    #[test]
+    fn test_sweep_negate_quirk() {
+        let mut square = Square::new(true);
+        square.write(2, 0xF0, false);
+        square.write(0, 0x19, false); // Period 1, negate, shift 1
+        square.write(4, 0x84, false);
+        square.clock_sweep();
+        assert!(square.enabled, "Negated sweep");
+
+        square.write(0, 0x11, false);
+        assert!(!square.enabled, "Clearing negate after use disables the channel");
+    }
+
+    #[test]
+    fn test_wave_volume() {
+        let wave_ram = [0xF0; 16];
+        let mut wave = Wave::new();
+        wave.write(0, 0x80, false);
+        wave.write(2, 0x20, false); // 100%
+        wave.write(3, 0xFF, false);
+        wave.write(4, 0x87, false);
+
+        let out: Vec<u8> = (0..16).map(|_| {wave.tick(&wave_ram); wave.output()}).collect();
+        assert!(out.contains(&15) && out.contains(&0), "Both nibbles are played");
+
+        wave.write(2, 0x40, false); // 50%
+        assert!(wave.output() <= 7, "Volume shift");
+        wave.write(2, 0x00, false);
+        assert_eq!(wave.output(), 0, "Muted");
+    }
+
+    #[test]
+    fn test_noise_lfsr() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xF0, false);
+        noise.write(3, 0x08, false); // Width mode, divisor 8
+        noise.write(4, 0x80, false);
+
+        // The 7 bits LFSR repeats every 127 clocks
+        let out: Vec<u8> = (0..8 * 254).map(|_| {noise.tick(); noise.output()}).collect();
+        assert_eq!(out[..8 * 127], out[8 * 127..], "7 bits period");
+        assert!(out.contains(&15) && out.contains(&0), "Noise output");
+    }
+
+    #[test]
+    fn test_wav_recording() {
+        let path = std::env::temp_dir().join(format!("oxide_test_{}.wav", std::process::id()));
+        let mut recorder = Recorder::create(&path, 48000, true).unwrap();
+        recorder.write([1.0, -1.0], [0.0, 0.5, 0.0, 0.0]).unwrap();
+        recorder.write([0.0, 2.0], [0.0; 4]).unwrap();
+        recorder.finish().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 8, "RIFF size");
+        assert_eq!(u16::from_le_bytes(data[22..24].try_into().unwrap()), 2, "Stereo");
+        assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 48000, "Sample rate");
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 8, "Data size");
+        let samples: Vec<i16> = data[44..].chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(samples, [i16::MAX, -i16::MAX, 0, i16::MAX], "Clamped PCM samples");
+
+        let ch2_path = Recorder::channel_path(&path, 2);
+        let ch2 = std::fs::read(&ch2_path).unwrap();
+        assert_eq!(u16::from_le_bytes(ch2[22..24].try_into().unwrap()), 1, "Mono channel file");
+        assert_eq!(i16::from_le_bytes([ch2[44], ch2[45]]), i16::MAX / 2, "Channel 2 sample");
+
+        std::fs::remove_file(&path).unwrap();
+        for n in 1..=4 {
+            std::fs::remove_file(Recorder::channel_path(&path, n)).unwrap();
+        }
+    }
+}
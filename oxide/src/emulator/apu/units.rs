@@ -0,0 +1,133 @@
+use crate::emulator::state::*;
+
+/*
+ * Units shared by several channels: length counter and volume envelope.
+ */
+
+#[derive(Debug, Copy, Clone)]
+pub(super) struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> Self {
+        LengthCounter { max, counter: 0, enabled: false }
+    }
+
+    // NRx1 length bits
+    pub fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    // Returns true when the channel has to be disabled
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+
+    // NRx4 write. When the next frame sequencer step does not clock the length,
+    // enabling it clocks it once more. Returns true when the channel has to be disabled.
+    pub fn write_control(&mut self, enable: bool, trigger: bool, extra_clock: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enable;
+        let mut disable = false;
+
+        if !was_enabled && enable && extra_clock && self.counter > 0 {
+            self.counter -= 1;
+            disable = self.counter == 0 && !trigger;
+        }
+
+        if trigger && self.counter == 0 {
+            self.counter = self.max;
+            if enable && extra_clock {
+                self.counter -= 1;
+            }
+        }
+        disable
+    }
+}
+
+// The maximum is fixed by the channel
+impl SaveState for LengthCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.counter);
+        w.bool(self.enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.counter = r.u16()?;
+        self.enabled = r.bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub(super) struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    pub volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    // NRx2 write
+    pub fn write(&mut self, value: u8) {
+        self.initial = value >> 4;
+        self.increase = value & 0b1000 != 0;
+        self.period = value & 0b111;
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = if self.period == 0 {8} else {self.period};
+    }
+
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period;
+        if self.increase && self.volume < 15 {
+            self.volume += 1;
+        } else if !self.increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.initial);
+        w.bool(self.increase);
+        w.u8(self.period);
+        w.u8(self.volume);
+        w.u8(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.initial = r.u8()?;
+        self.increase = r.bool()?;
+        self.period = r.u8()?;
+        self.volume = r.u8()?;
+        self.timer = r.u8()?;
+        Ok(())
+    }
+}
+
+// DAC power is controlled by the upper 5 bits of NRx2
+pub(super) fn dac_enabled(nrx2: u8) -> bool {
+    nrx2 & 0xF8 != 0
+}
@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/*
+ * WAV recording of the APU output.
+ * Samples are written as 16-bit PCM. The RIFF and data chunk sizes are
+ * patched when the recording is finished, or when the writer is dropped.
+ */
+
+const HEADER_SIZE: u32 = 44;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Could not create {}: {e}", path.display()))?;
+        let mut writer = WavWriter { file: BufWriter::new(file), data_len: 0, finished: false };
+
+        let block_align = channels * 2;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend(b"RIFF");
+        header.extend(0u32.to_le_bytes());                     // Patched on finish
+        header.extend(b"WAVEfmt ");
+        header.extend(16u32.to_le_bytes());
+        header.extend(1u16.to_le_bytes());                     // PCM
+        header.extend(channels.to_le_bytes());
+        header.extend(sample_rate.to_le_bytes());
+        header.extend((sample_rate * block_align as u32).to_le_bytes());
+        header.extend(block_align.to_le_bytes());
+        header.extend(16u16.to_le_bytes());                    // Bits per sample
+        header.extend(b"data");
+        header.extend(0u32.to_le_bytes());                     // Patched on finish
+        writer.file.write_all(&header).map_err(|e| e.to_string())?;
+        Ok(writer)
+    }
+
+    // Samples from -1.0 to 1.0, interleaved if there are several channels
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&value.to_le_bytes()).map_err(|e| e.to_string())?;
+        }
+        self.data_len += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<(), String> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        let mut patch = |offset: u64, value: u32| -> std::io::Result<()> {
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.write_all(&value.to_le_bytes())
+        };
+        patch(4, HEADER_SIZE - 8 + self.data_len).map_err(|e| e.to_string())?;
+        patch(40, self.data_len).map_err(|e| e.to_string())?;
+        self.file.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// Mixed stereo output, and optionally each channel in a mono file
+pub struct Recorder {
+    mix: WavWriter,
+    channels: Option<[WavWriter; 4]>,
+}
+
+impl Recorder {
+    // Channels are written next to the mix, as <name>_ch1.wav to <name>_ch4.wav
+    pub fn create(path: &Path, sample_rate: u32, split_channels: bool) -> Result<Self, String> {
+        let mix = WavWriter::create(path, 2, sample_rate)?;
+        let channels = if split_channels {
+            let writers = (1..=4)
+                .map(|n| WavWriter::create(&Self::channel_path(path, n), 1, sample_rate))
+                .collect::<Result<Vec<_>, _>>()?;
+            writers.try_into().ok()
+        } else {
+            None
+        };
+        Ok(Recorder { mix, channels })
+    }
+
+    pub fn channel_path(path: &Path, channel: usize) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{stem}_ch{channel}.wav"))
+    }
+
+    pub fn write(&mut self, mix: [f32; 2], channels: [f32; 4]) -> Result<(), String> {
+        self.mix.write(&mix)?;
+        if let Some(writers) = &mut self.channels {
+            for (writer, sample) in writers.iter_mut().zip(channels) {
+                writer.write(&[sample])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.mix.finish()?;
+        if let Some(writers) = &mut self.channels {
+            for writer in writers {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use super::units::*;
+use crate::emulator::state::*;
+
+/*
+ * Wave channel (CH3).
+ * Plays the 32 4-bit samples of the wave RAM (0xFF30-0xFF3F), upper nibble
+ * first, advancing every (2048 - frequency) * 2 T-cycles.
+ */
+
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Wave {
+    pub enabled: bool,
+    dac: bool,
+    volume_code: u8,
+    frequency: u16,
+    timer: u16,
+    position: u8,
+    sample: u8,
+    length: LengthCounter,
+}
+
+impl SaveState for Wave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.bool(self.dac);
+        w.u8(self.volume_code);
+        w.u16(self.frequency);
+        w.u16(self.timer);
+        w.u8(self.position);
+        w.u8(self.sample);
+        self.length.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.bool()?;
+        self.dac = r.bool()?;
+        self.volume_code = r.u8()?;
+        self.frequency = r.u16()?;
+        self.timer = r.u16()?;
+        self.position = r.u8()?;
+        self.sample = r.u8()?;
+        self.length.load_state(r)
+    }
+}
+
+impl Wave {
+    pub fn new() -> Self {
+        Wave {
+            enabled: false,
+            dac: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            length: LengthCounter::new(256),
+        }
+    }
+
+    // Power off resets everything but the length counter
+    pub fn reset(&mut self) {
+        *self = Wave { length: self.length, ..Wave::new() };
+    }
+
+    // reg is the register offset from NR30
+    pub fn write(&mut self, reg: u16, value: u8, extra_clock: bool) {
+        match reg {
+            0 => {
+                self.dac = value & 0x80 != 0;
+                if !self.dac {
+                    self.enabled = false;
+                }
+            },
+            1 => self.length.load(value),
+            2 => self.volume_code = (value >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8);
+                let trigger = value & 0x80 != 0;
+                if self.length.write_control(value & 0x40 != 0, trigger, extra_clock) {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.enabled = self.dac;
+                    self.position = 0;
+                    // The first sample is read after an additional delay
+                    self.timer = (2048 - self.frequency) * 2 + 6;
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // Length writes are still accepted while the APU is off
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    // Should be ticked every T cycle
+    pub fn tick(&mut self, wave_ram: &[u8]) {
+        if self.timer <= 1 {
+            self.timer = (2048 - self.frequency) * 2;
+            self.position = (self.position + 1) & 0x1F;
+            let byte = wave_ram[self.position as usize / 2];
+            self.sample = if self.position & 1 == 0 {byte >> 4} else {byte & 0xF};
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac
+    }
+
+    // Digital output, 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.volume_code == 0 {
+            return 0;
+        }
+        self.sample >> (self.volume_code - 1)
+    }
+}
@@ -21,7 +21,8 @@ impl fmt::Display for MicroOp {
             MicroOp::Prefix => "PREFIX".to_string(),
             MicroOp::PrefetchOnly => "PrefetchOnly".to_string(),
             MicroOp::ScheduleEI => "EI".to_string(),
-            MicroOp::Halt => "HALT".to_string()
+            MicroOp::Halt => "HALT".to_string(),
+            MicroOp::Stop => "STOP".to_string(),
         };
 
         write!(f, "{}", s)
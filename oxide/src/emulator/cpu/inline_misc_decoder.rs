@@ -35,8 +35,9 @@ impl Cpu {
 
     #[inline]
     pub fn decode_stop() -> VecDeque<MicroOp> {
-        error!("Error: Decoding an unimplemented instruction: STOP");
-        Self::decode_noop()
+        VecDeque::from(vec![
+            MicroOp::Stop
+        ])
     }
 
     #[inline]
@@ -28,6 +28,7 @@ pub enum MicroOp {
     PrefetchOnly,
     ScheduleEI,
     Halt,
+    Stop,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -243,7 +244,7 @@ impl Cpu {
             MicroOp::RetI { .. } => false,
             MicroOp::PrefetchOnly |
             MicroOp::Cpl | MicroOp::Daa | MicroOp::Ccf | MicroOp::Scf |
-            MicroOp::Prefix | MicroOp::Halt |
+            MicroOp::Prefix | MicroOp::Halt | MicroOp::Stop |
             MicroOp::ScheduleEI => true,
         };
 
@@ -316,6 +317,11 @@ impl Cpu {
             MicroOp::Halt => {
                 self.halted = true
             }
+            MicroOp::Stop => {
+                if !bus.try_speed_switch() {
+                    warn!("STOP mode is not emulated, executed as NOP");
+                }
+            }
         };
 
         if prefetch {
@@ -2,6 +2,7 @@ pub mod registers;
 pub mod micro_ops;
 pub mod decoder;
 pub mod displays;
+mod state;
 
 mod inline_ld_decoder;
 mod inline_alu_decoder;
@@ -84,6 +85,36 @@ impl Cpu {
             cond_ops: VecDeque::new()
         }
     }
+    // Register values left by the SGB boot ROM
+    pub fn new_sgb() -> Self {
+        Cpu {
+            a: 0x01,
+            f: 0x00,
+            b: 0x00,
+            c: 0x14,
+            d: 0x00,
+            e: 0x00,
+            h: 0xC0,
+            l: 0x60,
+            ..Self::new_noboot()
+        }
+    }
+
+    // Register values left by the CGB boot ROM
+    pub fn new_cgb() -> Self {
+        Cpu {
+            a: 0x11,
+            f: 0x80,
+            b: 0x00,
+            c: 0x00,
+            d: 0xFF,
+            e: 0x56,
+            h: 0x00,
+            l: 0x0D,
+            ..Self::new_noboot()
+        }
+    }
+
     pub fn new_boot() -> Self {
         Cpu {
             a: 0,
@@ -0,0 +1,258 @@
+use super::*;
+use crate::emulator::state::*;
+
+/*
+ * CPU save state.
+ * The micro-ops queued for the current instruction are saved, so that a
+ * state can be taken in the middle of an instruction. Enums are written as
+ * a tag followed by their fields.
+ */
+
+const REG8: [Reg8; 14] = [
+    Reg8::A, Reg8::F, Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L,
+    Reg8::W, Reg8::Z, Reg8::PCH, Reg8::PCL, Reg8::SPH, Reg8::SPL,
+];
+const REG16: [Reg16; 7] = [Reg16::BC, Reg16::DE, Reg16::HL, Reg16::SP, Reg16::PC, Reg16::AF, Reg16::WZ];
+const CONDITIONS: [Condition; 4] = [Condition::Z, Condition::C, Condition::NZ, Condition::NC];
+const SHIFTS: [ShiftType; 4] = [ShiftType::R, ShiftType::RC, ShiftType::SA, ShiftType::SL];
+
+fn read_reg8(r: &mut StateReader) -> Result<Reg8, String> {
+    let tag = r.u8()?;
+    REG8.get(tag as usize).copied().ok_or_else(|| invalid_tag("8 bits register", tag))
+}
+
+fn read_reg16(r: &mut StateReader) -> Result<Reg16, String> {
+    let tag = r.u8()?;
+    REG16.get(tag as usize).copied().ok_or_else(|| invalid_tag("16 bits register", tag))
+}
+
+fn read_condition(r: &mut StateReader) -> Result<Condition, String> {
+    let tag = r.u8()?;
+    CONDITIONS.get(tag as usize).copied().ok_or_else(|| invalid_tag("condition", tag))
+}
+
+fn read_shift(r: &mut StateReader) -> Result<ShiftType, String> {
+    let tag = r.u8()?;
+    SHIFTS.get(tag as usize).copied().ok_or_else(|| invalid_tag("shift type", tag))
+}
+
+fn write_target(w: &mut StateWriter, target: RWTarget) {
+    match target {
+        RWTarget::Reg8(reg) => {w.u8(0); w.u8(reg as u8)},
+        RWTarget::Reg16(reg) => {w.u8(1); w.u8(reg as u8)},
+        RWTarget::Indirect16(reg) => {w.u8(2); w.u8(reg as u8)},
+        RWTarget::Indirect16I(reg) => {w.u8(3); w.u8(reg as u8)},
+        RWTarget::Indirect16D(reg) => {w.u8(4); w.u8(reg as u8)},
+        RWTarget::HRAM(reg) => {w.u8(5); w.u8(reg as u8)},
+        RWTarget::Value(value) => {w.u8(6); w.u16(value)},
+        RWTarget::IME => w.u8(7),
+    }
+}
+
+fn read_target(r: &mut StateReader) -> Result<RWTarget, String> {
+    Ok(match r.u8()? {
+        0 => RWTarget::Reg8(read_reg8(r)?),
+        1 => RWTarget::Reg16(read_reg16(r)?),
+        2 => RWTarget::Indirect16(read_reg16(r)?),
+        3 => RWTarget::Indirect16I(read_reg16(r)?),
+        4 => RWTarget::Indirect16D(read_reg16(r)?),
+        5 => RWTarget::HRAM(read_reg8(r)?),
+        6 => RWTarget::Value(r.u16()?),
+        7 => RWTarget::IME,
+        tag => return Err(invalid_tag("operand", tag)),
+    })
+}
+
+fn write_operation(w: &mut StateWriter, ope: Operation) {
+    let mut binary = |tag, left, right, dest, mask| {
+        w.u8(tag);
+        write_target(w, left);
+        write_target(w, right);
+        write_target(w, dest);
+        w.u8(mask);
+    };
+
+    match ope {
+        Operation::Add{left, right, dest, mask} => binary(0, left, right, dest, mask),
+        Operation::Sub{left, right, dest, mask} => binary(1, left, right, dest, mask),
+        Operation::Adc{left, right, dest, mask} => binary(2, left, right, dest, mask),
+        Operation::Sbc{left, right, dest, mask} => binary(3, left, right, dest, mask),
+        Operation::Ads{left, right, dest, mask} => binary(4, left, right, dest, mask),
+        Operation::And{left, right, dest, mask} => binary(5, left, right, dest, mask),
+        Operation::Or{left, right, dest, mask} => binary(6, left, right, dest, mask),
+        Operation::Xor{left, right, dest, mask} => binary(7, left, right, dest, mask),
+        Operation::Inc{source, dest, mask} | Operation::Dec{source, dest, mask} | Operation::Swp{source, dest, mask} => {
+            w.u8(match ope {Operation::Inc{..} => 8, Operation::Dec{..} => 9, _ => 10});
+            write_target(w, source);
+            write_target(w, dest);
+            w.u8(mask);
+        },
+        Operation::Rsh{shift, source, dest, mask} | Operation::Lsh{shift, source, dest, mask} => {
+            w.u8(if matches!(ope, Operation::Rsh{..}) {11} else {12});
+            w.u8(shift as u8);
+            write_target(w, source);
+            write_target(w, dest);
+            w.u8(mask);
+        },
+        Operation::Bit{source, bit, mask} => {
+            w.u8(13);
+            write_target(w, source);
+            w.u8(bit);
+            w.u8(mask);
+        },
+        Operation::Rsb{source, dest, bit, value} => {
+            w.u8(14);
+            write_target(w, source);
+            write_target(w, dest);
+            w.u8(bit);
+            w.u8(value);
+        },
+    }
+}
+
+fn read_operation(r: &mut StateReader) -> Result<Operation, String> {
+    Ok(match r.u8()? {
+        tag @ 0..=7 => {
+            let (left, right, dest, mask) = (read_target(r)?, read_target(r)?, read_target(r)?, r.u8()?);
+            match tag {
+                0 => Operation::Add{left, right, dest, mask},
+                1 => Operation::Sub{left, right, dest, mask},
+                2 => Operation::Adc{left, right, dest, mask},
+                3 => Operation::Sbc{left, right, dest, mask},
+                4 => Operation::Ads{left, right, dest, mask},
+                5 => Operation::And{left, right, dest, mask},
+                6 => Operation::Or{left, right, dest, mask},
+                _ => Operation::Xor{left, right, dest, mask},
+            }
+        },
+        tag @ 8..=10 => {
+            let (source, dest, mask) = (read_target(r)?, read_target(r)?, r.u8()?);
+            match tag {
+                8 => Operation::Inc{source, dest, mask},
+                9 => Operation::Dec{source, dest, mask},
+                _ => Operation::Swp{source, dest, mask},
+            }
+        },
+        tag @ 11..=12 => {
+            let (shift, source, dest, mask) = (read_shift(r)?, read_target(r)?, read_target(r)?, r.u8()?);
+            if tag == 11 {
+                Operation::Rsh{shift, source, dest, mask}
+            } else {
+                Operation::Lsh{shift, source, dest, mask}
+            }
+        },
+        13 => Operation::Bit{source: read_target(r)?, bit: r.u8()?, mask: r.u8()?},
+        14 => Operation::Rsb{source: read_target(r)?, dest: read_target(r)?, bit: r.u8()?, value: r.u8()?},
+        tag => return Err(invalid_tag("operation", tag)),
+    })
+}
+
+fn write_micro_op(w: &mut StateWriter, op: MicroOp) {
+    match op {
+        MicroOp::DataMove{source, dest, prefetch} => {
+            w.u8(0);
+            write_target(w, source);
+            write_target(w, dest);
+            w.bool(prefetch);
+        },
+        MicroOp::Operation{ope, prefetch} => {
+            w.u8(1);
+            write_operation(w, ope);
+            w.bool(prefetch);
+        },
+        MicroOp::ReadIMM{prefetch} => {w.u8(2); w.bool(prefetch)},
+        MicroOp::ReadLSB{prefetch} => {w.u8(3); w.bool(prefetch)},
+        MicroOp::ReadMSB{prefetch} => {w.u8(4); w.bool(prefetch)},
+        MicroOp::ReadMSBCC{cc} => {w.u8(5); w.u8(cc as u8)},
+        MicroOp::ReadLSBCC{cc} => {w.u8(6); w.u8(cc as u8)},
+        MicroOp::CheckCC{cc} => {w.u8(7); w.u8(cc as u8)},
+        MicroOp::Cpl => w.u8(8),
+        MicroOp::Daa => w.u8(9),
+        MicroOp::Ccf => w.u8(10),
+        MicroOp::Scf => w.u8(11),
+        MicroOp::Prefix => w.u8(12),
+        MicroOp::RetI => w.u8(13),
+        MicroOp::PrefetchOnly => w.u8(14),
+        MicroOp::ScheduleEI => w.u8(15),
+        MicroOp::Halt => w.u8(16),
+        MicroOp::Stop => w.u8(17),
+    }
+}
+
+fn read_micro_op(r: &mut StateReader) -> Result<MicroOp, String> {
+    Ok(match r.u8()? {
+        0 => MicroOp::DataMove{source: read_target(r)?, dest: read_target(r)?, prefetch: r.bool()?},
+        1 => MicroOp::Operation{ope: read_operation(r)?, prefetch: r.bool()?},
+        2 => MicroOp::ReadIMM{prefetch: r.bool()?},
+        3 => MicroOp::ReadLSB{prefetch: r.bool()?},
+        4 => MicroOp::ReadMSB{prefetch: r.bool()?},
+        5 => MicroOp::ReadMSBCC{cc: read_condition(r)?},
+        6 => MicroOp::ReadLSBCC{cc: read_condition(r)?},
+        7 => MicroOp::CheckCC{cc: read_condition(r)?},
+        8 => MicroOp::Cpl,
+        9 => MicroOp::Daa,
+        10 => MicroOp::Ccf,
+        11 => MicroOp::Scf,
+        12 => MicroOp::Prefix,
+        13 => MicroOp::RetI,
+        14 => MicroOp::PrefetchOnly,
+        15 => MicroOp::ScheduleEI,
+        16 => MicroOp::Halt,
+        17 => MicroOp::Stop,
+        tag => return Err(invalid_tag("micro-op", tag)),
+    })
+}
+
+fn write_queue(w: &mut StateWriter, ops: &VecDeque<MicroOp>) {
+    w.u8(ops.len() as u8);
+    for op in ops {
+        write_micro_op(w, *op);
+    }
+}
+
+fn read_queue(r: &mut StateReader) -> Result<VecDeque<MicroOp>, String> {
+    let len = r.u8()?;
+    (0..len).map(|_| read_micro_op(r)).collect()
+}
+
+impl SaveState for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        for reg in [self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.w, self.z] {
+            w.u8(reg);
+        }
+        w.u16(self.sp);
+        w.u16(self.pc);
+        w.bool(self.ime);
+        w.u8(self.ir);
+        w.u16(self.ir_pc);
+        w.bool(self.halted);
+
+        w.bool(self.prefix);
+        w.bool(self.ei_next);
+        write_queue(w, &self.next_ops);
+        write_queue(w, &self.cond_ops);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for reg in [&mut self.a, &mut self.f, &mut self.b, &mut self.c, &mut self.d,
+                    &mut self.e, &mut self.h, &mut self.l, &mut self.w, &mut self.z] {
+            *reg = r.u8()?;
+        }
+        self.sp = r.u16()?;
+        self.pc = r.u16()?;
+        self.ime = r.bool()?;
+        self.ir = r.u8()?;
+        self.ir_pc = r.u16()?;
+        self.halted = r.bool()?;
+
+        self.prefix = r.bool()?;
+        self.ei_next = r.bool()?;
+        self.next_ops = read_queue(r)?;
+        self.cond_ops = read_queue(r)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/state.rs"]
+mod state_tests;
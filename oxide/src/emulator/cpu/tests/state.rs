@@ -0,0 +1,62 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::cpu::*;
+    use crate::emulator::state::*;
+
+    fn round_trip(cpu: &Cpu) -> Cpu {
+        let mut w = StateWriter::default();
+        cpu.save_state(&mut w);
+        let data = w.into_bytes();
+
+        let mut r = StateReader::new(&data);
+        let mut loaded = Cpu::new_boot();
+        loaded.load_state(&mut r).unwrap();
+        assert!(r.is_empty(), "Whole state read");
+        loaded
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut cpu = Cpu::new_noboot();
+        cpu.w = 0x12;
+        cpu.ime = true;
+        cpu.ir = 0xCB;
+        cpu.ir_pc = 0x1234;
+        cpu.prefix = true;
+        cpu.ei_next = true;
+
+        let loaded = round_trip(&cpu);
+        assert_eq!(format!("{loaded:?}"), format!("{cpu:?}"));
+    }
+
+    #[test]
+    fn test_micro_ops() {
+        // Every instruction, with the conditional micro-ops of jumps, calls and returns
+        for ir in 0..=0xFF {
+            let mut cpu = Cpu::new_noboot();
+            cpu.next_ops = Cpu::decode(ir);
+            cpu.cond_ops = Cpu::decode_condition(ir);
+            let loaded = round_trip(&cpu);
+            assert_eq!(format!("{loaded:?}"), format!("{cpu:?}"), "Opcode {ir:#04X}");
+
+            cpu.next_ops = Cpu::decode_prefix_opcode(ir);
+            let loaded = round_trip(&cpu);
+            assert_eq!(format!("{loaded:?}"), format!("{cpu:?}"), "Opcode 0xCB {ir:#04X}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        let mut w = StateWriter::default();
+        Cpu::new_noboot().save_state(&mut w);
+        let mut data = w.into_bytes();
+        // The state ends with the lengths of both queues
+        data.truncate(data.len() - 2);
+        data.extend([1, 0xFF, 0]);
+
+        let mut cpu = Cpu::new_noboot();
+        let res = cpu.load_state(&mut StateReader::new(&data));
+        assert_eq!(res, Err(invalid_tag("micro-op", 0xFF)));
+    }
+}
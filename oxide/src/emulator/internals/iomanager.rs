@@ -1,10 +1,10 @@
-use crate::emulator::memory::regdefines::JOYP;
-use crate::emulator::memory::Bus;
-use crate::emulator::ppu::{Frame, Ppu};
-use crossbeam_channel::Sender;
+use crate::emulator::apu::AudioChunk;
+use crate::emulator::ppu::Frame;
+use crossbeam_channel::{Sender, TrySendError};
 use log::warn;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /* Joypad Inputs bits:
  * A      -> 0
@@ -18,42 +18,127 @@ use std::sync::Arc;
  */
 
 
+// Save state hotkeys, requested by the frontend
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StateRequest {
+    Save = 1,
+    Load = 2,
+}
+
+// 70224 T-cycles at 4.194304 MHz
+const FRAME_DURATION: Duration = Duration::from_nanos(16_742_706);
+
 pub struct IoManager {
     pub tx_frame: Sender<Frame>,
+    pub tx_audio: Option<Sender<AudioChunk>>, // Samples are dropped without audio output
     pub joyp: Arc<AtomicU8>,
+    pub rumble: Arc<AtomicBool>, // Cartridge rumble motor state, read by the frontend
+    pub throttle: bool,     // Limit the emulation speed to the real hardware frame rate
+    next_frame: Instant,
+    last_lines: u8,         // JOYP input lines during the last tick
+    pub serial_log: Option<Vec<u8>>, // Bytes sent over the link cable, if recorded
+    stop: Arc<AtomicBool>,  // Set by the frontend when the emulator should exit
+    state_request: Arc<AtomicU8>, // StateRequest set by the frontend, 0 if none
 }
 
 
 impl IoManager {
-    pub fn new(tx_frame: Sender<Frame>, joyp: Arc<AtomicU8>) -> IoManager {
+    pub fn new(tx_frame: Sender<Frame>, joyp: Arc<AtomicU8>, rumble: Arc<AtomicBool>, throttle: bool) -> IoManager {
         IoManager {
             tx_frame,
-            joyp
+            tx_audio: None,
+            joyp,
+            rumble,
+            throttle,
+            next_frame: Instant::now(),
+            last_lines: 0xF,
+            serial_log: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            state_request: Arc::new(AtomicU8::new(0)),
         }
     }
     
     pub fn send_frame(&mut self, frame: Frame) {
+        if self.throttle {
+            self.wait_next_frame();
+        }
+
         if self.tx_frame.try_send(frame).is_err() {
             warn!("Dropped a frame as UI is not ready")
         }
     }
     
+    pub fn send_audio(&mut self, chunk: AudioChunk) {
+        if let Some(tx) = &self.tx_audio {
+            match tx.try_send(chunk) {
+                Err(TrySendError::Full(_)) => warn!("Dropped audio samples as UI is not ready"),
+                // The frontend runs without audio output
+                Err(TrySendError::Disconnected(_)) => self.tx_audio = None,
+                Ok(()) => (),
+            }
+        }
+    }
+
+    fn wait_next_frame(&mut self) {
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+            self.next_frame += FRAME_DURATION;
+        } else {
+            // Running late: do not try to catch up
+            self.next_frame = now + FRAME_DURATION;
+        }
+    }
+
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn should_stop(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn state_request_handle(&self) -> Arc<AtomicU8> {
+        self.state_request.clone()
+    }
+
+    pub fn take_state_request(&self) -> Option<StateRequest> {
+        if self.state_request.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        match self.state_request.swap(0, Ordering::Relaxed) {
+            1 => Some(StateRequest::Save),
+            2 => Some(StateRequest::Load),
+            _ => None,
+        }
+    }
+
+    pub fn set_rumble(&self, on: bool) {
+        self.rumble.store(on, Ordering::Relaxed);
+    }
+
     pub fn get_joystate(&self) -> u8 {
         self.joyp.load(Ordering::Relaxed)
     }
 
-    pub fn tick(&mut self, bus: &mut Bus, frame: Option<Frame>) {
-        // Joypad register computation
-        let joystate: u8 = self.joyp.load(Ordering::Relaxed); // Get state from sdl thread
-        let sel = bus.read(JOYP) & 0x30;                 // Get Register selection bits
+    // Returns the JOYP register value for the given selection bits
+    pub fn get_joyp(&self, sel: u8) -> u8 {
+        let joystate: u8 = self.get_joystate();         // Get state from sdl thread
         let buttons = (sel & 0b0010_0000) == 0;         // Is buttons selected
         let dpad = (sel & 0b0001_0000) == 0;            // Is DPad selected
         let mut result: u8 = 0;
-        
-        if buttons {result |=  joystate       & 0xF}                
+
+        if buttons {result |=  joystate       & 0xF}
         if dpad    {result |= (joystate >> 4) & 0xF}
-        
-        result = ((!result) & 0xF) | sel | 0xC0;             // Recompute the register
-        bus.write(JOYP, result);
+
+        ((!result) & 0xF) | (sel & 0x30) | 0xC0          // Recompute the register
+    }
+
+    // Returns true when one of the JOYP input lines went from high to low
+    pub fn tick(&mut self, sel: u8) -> bool {
+        let lines = self.get_joyp(sel) & 0xF;
+        let falling = self.last_lines & !lines != 0;
+        self.last_lines = lines;
+        falling
     }
 }
\ No newline at end of file
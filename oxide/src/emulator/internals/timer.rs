@@ -1,6 +1,7 @@
 use log::{debug, info};
 use crate::emulator::memory::Bus;
 use crate::emulator::cpu::interrupt::*;
+use crate::emulator::state::*;
 
 const DIV: u16 = 0xFF04;
 const TIMA: u16 = 0xFF05;
@@ -13,6 +14,19 @@ pub struct Timer {
     last_and_result: bool
 }
 
+impl SaveState for Timer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.cycles);
+        w.bool(self.last_and_result);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.cycles = r.u16()?;
+        self.last_and_result = r.bool()?;
+        Ok(())
+    }
+}
+
 impl Timer {
     
     // Should Be ticked every T cycle
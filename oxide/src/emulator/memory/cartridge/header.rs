@@ -0,0 +1,234 @@
+use std::fmt;
+
+/*
+ * Cartridge header (0x0100-0x014F).
+ * Parsing fails on ROMs too small for their header or with invalid size
+ * bytes. The logo and header checksum are checked like the boot ROM does,
+ * the global checksum is only informative as no hardware checks it.
+ */
+
+pub const HEADER_END: usize = 0x150;
+
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// Old licensee code meaning that the new licensee code is used
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    Truncated(usize),                            // ROM length
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    RomSizeMismatch { expected: usize, actual: usize },
+    BadLogo,
+    BadHeaderChecksum { expected: u8, actual: u8 },
+    UnsupportedType(u8),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::Truncated(len) => write!(f, "ROM is too small to contain a header: {len} bytes"),
+            HeaderError::InvalidRomSize(v) => write!(f, "Invalid ROM size value: {v:#04X}"),
+            HeaderError::InvalidRamSize(v) => write!(f, "Invalid RAM size value: {v:#04X}"),
+            HeaderError::RomSizeMismatch { expected, actual } =>
+                write!(f, "ROM is truncated: header declares {expected} bytes, file has {actual}"),
+            HeaderError::BadLogo => write!(f, "Nintendo logo does not match, the ROM is corrupt"),
+            HeaderError::BadHeaderChecksum { expected, actual } =>
+                write!(f, "Header checksum mismatch: expected {expected:#04X}, computed {actual:#04X}"),
+            HeaderError::UnsupportedType(v) => write!(f, "Unimplemented MBC Type: {v:#04X}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub raw_title: [u8; 16],  // Whole title area, including the CGB flag
+    pub manufacturer: String, // Only present in late titles
+    pub old_licensee: u8,
+    pub new_licensee: String,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size: usize,      // In bytes
+    pub ram_size: usize,      // In bytes
+    pub destination: u8,      // 0x00: Japan, 0x01: Overseas
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self, HeaderError> {
+        if rom.len() < HEADER_END {
+            return Err(HeaderError::Truncated(rom.len()));
+        }
+
+        let rom_size = match rom[0x148] {
+            v @ 0x00..=0x08 => 0x8000 << v,
+            0x52 => 72 * 0x4000,
+            0x53 => 80 * 0x4000,
+            0x54 => 96 * 0x4000,
+            v => return Err(HeaderError::InvalidRomSize(v)),
+        };
+        let ram_size = match rom[0x149] {
+            0x00 => 0,
+            0x01 => 2 * 1024,
+            0x02 => 8 * 1024,
+            0x03 => 32 * 1024,
+            0x04 => 128 * 1024,
+            0x05 => 64 * 1024,
+            v => return Err(HeaderError::InvalidRamSize(v)),
+        };
+        if rom.len() < rom_size {
+            return Err(HeaderError::RomSizeMismatch { expected: rom_size, actual: rom.len() });
+        }
+
+        // CGB titles use the end of the title area for the manufacturer code and CGB flag
+        let cgb_flag = rom[0x143];
+        let title_end = if cgb_flag & 0x80 != 0 {0x13F} else {0x144};
+
+        Ok(CartridgeHeader {
+            title: Self::ascii(&rom[0x134..title_end]),
+            raw_title: rom[0x134..0x144].try_into().unwrap(),
+            manufacturer: Self::ascii(&rom[0x13F..0x143]),
+            old_licensee: rom[0x14B],
+            new_licensee: Self::ascii(&rom[0x144..0x146]),
+            cgb_flag,
+            sgb_flag: rom[0x146],
+            cartridge_type: rom[0x147],
+            rom_size,
+            ram_size,
+            destination: rom[0x14A],
+            version: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: u16::from_be_bytes([rom[0x14E], rom[0x14F]]),
+        })
+    }
+
+    // Performs the checks done by the boot ROM
+    pub fn validate(&self, rom: &[u8]) -> Result<(), HeaderError> {
+        if rom[0x104..0x134] != NINTENDO_LOGO {
+            return Err(HeaderError::BadLogo);
+        }
+
+        let actual = Self::compute_header_checksum(rom);
+        if actual != self.header_checksum {
+            return Err(HeaderError::BadHeaderChecksum { expected: self.header_checksum, actual });
+        }
+        Ok(())
+    }
+
+    pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[0x134..0x14D].iter().fold(0u8, |acc, v| acc.wrapping_sub(*v).wrapping_sub(1))
+    }
+
+    // Sum of all ROM bytes, except the global checksum itself
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter().enumerate()
+            .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+            .fold(0u16, |acc, (_, v)| acc.wrapping_add(*v as u16))
+    }
+
+    pub fn global_checksum_valid(&self, rom: &[u8]) -> bool {
+        Self::compute_global_checksum(rom) == self.global_checksum
+    }
+
+    pub fn licensee(&self) -> String {
+        if self.old_licensee == USE_NEW_LICENSEE {
+            self.new_licensee.clone()
+        } else {
+            format!("{:02X}", self.old_licensee)
+        }
+    }
+
+    // Used by the CGB boot ROM to colorize Nintendo DMG titles
+    pub fn title_checksum(&self) -> u8 {
+        self.raw_title.iter().fold(0u8, |acc, v| acc.wrapping_add(*v))
+    }
+
+    pub fn is_nintendo(&self) -> bool {
+        self.old_licensee == 0x01 || (self.old_licensee == USE_NEW_LICENSEE && self.new_licensee == "01")
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    // The SGB flag is ignored if the old licensee code is not 0x33
+    pub fn is_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee == USE_NEW_LICENSEE
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cartridge_type, 0x03 | 0x06 | 0x09 | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN",
+        }
+    }
+
+    // Printable part of a header string, up to the first NUL
+    fn ascii(bytes: &[u8]) -> String {
+        bytes.iter()
+            .take_while(|b| **b != 0)
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' {*b as char} else {'?'})
+            .collect()
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Title:        {}", self.title)?;
+        writeln!(f, "Manufacturer: {}", self.manufacturer)?;
+        writeln!(f, "Licensee:     {}", self.licensee())?;
+        writeln!(f, "Type:         {} ({:#04X})", self.type_name(), self.cartridge_type)?;
+        writeln!(f, "ROM size:     {} KiB", self.rom_size / 1024)?;
+        writeln!(f, "RAM size:     {} KiB", self.ram_size / 1024)?;
+        writeln!(f, "CGB flag:     {:#04X}", self.cgb_flag)?;
+        writeln!(f, "SGB flag:     {:#04X}", self.sgb_flag)?;
+        writeln!(f, "Destination:  {}", if self.destination == 0 {"Japan"} else {"Overseas"})?;
+        writeln!(f, "Version:      {}", self.version)?;
+        write!(f, "Checksums:    header {:#04X}, global {:#06X}", self.header_checksum, self.global_checksum)
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/header.rs"]
+mod header_tests;
@@ -1,85 +1,128 @@
 use super::*;
 
+/*
+ * MBC1 (header types 0x01-0x03).
+ * BANK1 (0x2000-0x3FFF) holds the 5 lower ROM bank bits, 0 being mapped to 1.
+ * BANK2 (0x4000-0x5FFF) holds 2 bits used as upper ROM bank bits, and as
+ * RAM bank number. In mode 1 (0x6000-0x7FFF), BANK2 also applies to the
+ * 0x0000-0x3FFF area and to the RAM, otherwise they use bank 0.
+ * Unused bank bits are ignored according to the ROM and RAM sizes.
+ *
+ * MBC1M multicarts (1 MiB collections) wire the upper bits register to ROM
+ * address lines 18-19 instead of 19-20, so only 4 bits of the ROM bank
+ * register are used. Each game lives in 16 banks and starts with a Nintendo logo.
+ */
+
+const MULTICART_SIZE: usize = 0x100000;
+
 pub struct Mbc1 {
     ram_enable: bool,
-    rom_bank: u8,
-    ram_bank: u8,
+    bank1: u8,      // Lower ROM bank bits
+    bank2: u8,      // Upper ROM bank bits or RAM bank
     bank_mode: bool,
-    
+
     rom_count: usize,
-    ram_count: usize,
+    multicart: bool,
+}
+
+impl SaveState for Mbc1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enable);
+        w.u8(self.bank1);
+        w.u8(self.bank2);
+        w.bool(self.bank_mode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.ram_enable = r.bool()?;
+        self.bank1 = r.u8()?;
+        self.bank2 = r.u8()?;
+        self.bank_mode = r.bool()?;
+        Ok(())
+    }
 }
 
 impl Mbc for Mbc1 {
     fn read(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8 {
-        match (addr, self.bank_mode, self.ram_enable) {
-            (0x0000..0x4000, false, _) => rom[addr as usize],
-            (0x0000..0x4000, true, _) => {
-                let bank = self.ram_bank << 5;
-                rom[bank as usize * 0x4000 + addr as usize]
-            }
-            (0x4000..0x8000, _, _) => {
-                let mut bank = self.ram_bank & 0b11111;
-                if bank == 0 {bank = 1};
-                rom[bank as usize * 0x4000 + (addr - 0x4000) as usize]
-            }
-            (0xA000..0xC000, _, false) => {
-                0xFF
+        match addr {
+            0x0000..0x4000 => {
+                let bank = if self.bank_mode {self.upper_bank()} else {0};
+                rom[(bank % self.rom_count) * 0x4000 + addr as usize]
+            },
+            0x4000..0x8000 => {
+                let bank = (self.upper_bank() | self.lower_bank()) % self.rom_count;
+                rom[bank * 0x4000 + (addr - 0x4000) as usize]
             },
-            (0xA000..0xC000, false, true) => {
-                ram[addr as usize - 0xA000]
+            0xA000..0xC000 => {
+                if !self.ram_enable || ram.is_empty() {
+                    return 0xFF;
+                }
+                ram[self.ram_addr(ram, addr)]
             },
-            (0xA000..0xC000, true, true) => {
-                if !self.ram_enable { return 0xFF }
-                let bank = self.ram_bank & 0b11;
-                ram[(addr as usize - 0xA000) + 0x2000 * bank as usize]
-            }
-            (_, _, _) => panic!("Should be unreachable. Addr: {addr:#06X}"),
+            _ => panic!("Should be unreachable. Addr: {addr:#06X}"),
         }
     }
 
-    fn write(&mut self, ram: &mut [u8], addr: u16, mut value: u8) -> () {
+    fn write(&mut self, ram: &mut [u8], addr: u16, value: u8) {
         match addr {
-            0x0000..0x2000 => self.ram_enable =  value & 0xF == 0xA,
+            0x0000..0x2000 => self.ram_enable = value & 0xF == 0xA,
             0x2000..0x4000 => {
-                value = value & 0b11111;
-                if value == 0 {value = 1};
-                let mask = (self.rom_count.next_power_of_two() - 1) as u8;
-                value &= mask;
-                self.rom_bank = value;
+                // The zero check uses the 5 bits, even when the ROM is smaller
+                self.bank1 = value & 0b11111;
+                if self.bank1 == 0 {self.bank1 = 1};
             },
-            0x4000..0x6000 => {
-                self.ram_count = value as usize;
+            0x4000..0x6000 => self.bank2 = value & 0b11,
+            0x6000..0x8000 => self.bank_mode = value & 1 != 0,
+            0xA000..0xC000 if self.ram_enable && !ram.is_empty() => {
+                ram[self.ram_addr(ram, addr)] = value;
             },
-            0x6000..0x8000 => self.bank_mode = (value & 1) != 0,
-            0xA000..0xC000 => {
-                let bank = self.ram_bank & 0b11;
-                if bank as usize >= self.ram_count {
-                    ()
-                } else if self.ram_enable {
-                    ram[(addr as usize - 0xA000) + 0x2000 * bank as usize] = value;
-                }
-            }
             _ => ()
         }
     }
 
-    fn is_writeable(&self, addr: u16) -> bool {
-        let bank = self.ram_bank & 0b11;
-        bank < self.ram_bank
+    fn is_writeable(&self, _addr: u16) -> bool {
+        true
     }
 }
 
 impl Mbc1 {
-    pub fn new(rom_count: usize, ram_count: usize) -> Self {
+    pub fn new(rom_count: usize, multicart: bool) -> Self {
         Self {
             ram_enable: false,
-            rom_bank: 1,
-            ram_bank: 0,
+            bank1: 1,
+            bank2: 0,
             bank_mode: false,
-            
+
             rom_count,
-            ram_count
+            multicart,
         }
     }
-}
\ No newline at end of file
+
+    // Multicarts have a Nintendo logo at the start of every game, including bank 0x10
+    pub fn is_multicart(rom: &[u8]) -> bool {
+        let logo = 0x10 * 0x4000 + 0x104;
+        rom.len() == MULTICART_SIZE && rom[logo..logo + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+    }
+
+    // ROM bank bits driven by BANK2
+    fn upper_bank(&self) -> usize {
+        let shift = if self.multicart {4} else {5};
+        (self.bank2 as usize) << shift
+    }
+
+    // ROM bank bits driven by BANK1
+    fn lower_bank(&self) -> usize {
+        let mask = if self.multicart {0b1111} else {0b11111};
+        (self.bank1 & mask) as usize
+    }
+
+    // RAM smaller than 32 KiB ignores the bank bits
+    fn ram_addr(&self, ram: &[u8], addr: u16) -> usize {
+        let bank = if self.bank_mode {self.bank2 as usize} else {0};
+        (bank * 0x2000 + (addr - 0xA000) as usize) % ram.len()
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/mbc1.rs"]
+mod mbc1_tests;
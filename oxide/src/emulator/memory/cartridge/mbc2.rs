@@ -0,0 +1,81 @@
+use super::*;
+
+/*
+ * MBC2 (header types 0x05-0x06).
+ * 4 bits ROM bank number and 512x4 bits of RAM inside the MBC.
+ * Address bit 8 selects between the RAM enable and ROM bank registers.
+ * Only the lower nibble of the RAM is wired, the upper one reads as 1.
+ * The RAM is echoed in the whole 0xA000-0xBFFF range.
+ */
+
+pub const MBC2_RAM_SIZE: usize = 512;
+
+pub struct Mbc2 {
+    ram_enable: bool,
+    rom_bank: u8,
+
+    rom_count: usize,
+}
+
+impl SaveState for Mbc2 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enable);
+        w.u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.ram_enable = r.bool()?;
+        self.rom_bank = r.u8()?;
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..0x4000 => rom[addr as usize],
+            0x4000..0x8000 => {
+                let bank = self.rom_bank as usize % self.rom_count;
+                rom[bank * 0x4000 + (addr - 0x4000) as usize]
+            },
+            0xA000..0xC000 => {
+                if !self.ram_enable {
+                    return 0xFF;
+                }
+                ram[addr as usize % MBC2_RAM_SIZE] | 0xF0
+            },
+            _ => panic!("Should be unreachable. Addr: {addr:#06X}"),
+        }
+    }
+
+    fn write(&mut self, ram: &mut [u8], addr: u16, value: u8) {
+        match addr {
+            0x0000..0x4000 if addr & 0x100 == 0 => self.ram_enable = value & 0xF == 0xA,
+            0x0000..0x4000 => {
+                self.rom_bank = value & 0xF;
+                if self.rom_bank == 0 {self.rom_bank = 1};
+            },
+            0xA000..0xC000 if self.ram_enable => ram[addr as usize % MBC2_RAM_SIZE] = value & 0x0F,
+            _ => ()
+        }
+    }
+
+    fn is_writeable(&self, _addr: u16) -> bool {
+        true
+    }
+}
+
+impl Mbc2 {
+    pub fn new(rom_count: usize) -> Self {
+        Self {
+            ram_enable: false,
+            rom_bank: 1,
+
+            rom_count,
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/mbc2.rs"]
+mod mbc2_tests;
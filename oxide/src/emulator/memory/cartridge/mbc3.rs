@@ -0,0 +1,346 @@
+use super::*;
+use log::warn;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+ * MBC3 (header types 0x0F-0x13).
+ * 7 bits ROM bank number, 4 RAM banks, and an optional real time clock
+ * mapped in place of the RAM when its registers are selected (0x08-0x0C).
+ * The clock is ticked with emulated cycles and read through latched copies.
+ *
+ * Save files end with the RTC block used by BGB and VBA-M: current then
+ * latched registers as 32 bits little endian values, and a 64 bits UNIX
+ * timestamp (some emulators write a 32 bits one) used to catch up on load.
+ */
+
+// M-cycles per RTC second
+pub const RTC_CYCLES_PER_SECOND: u32 = 1 << 20;
+
+// RTC_DH bits
+const RTC_DAY_HIGH: u8 = 0b0000_0001;
+const RTC_HALT: u8 = 0b0100_0000;
+const RTC_CARRY: u8 = 0b1000_0000;
+
+const RTC_SAVE_LEN: usize = 48;
+const RTC_SAVE_LEN_32: usize = 44;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RtcRegs {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub day_low: u8,  // Lower 8 bits of the day counter
+    pub day_high: u8, // Day counter bit 8, halt and carry flags
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Rtc {
+    pub regs: RtcRegs,
+    pub latched: RtcRegs,
+    cycles: u32,      // M-cycles since the last second
+    latch_armed: bool, // Was 0x00 written to the latch register ?
+}
+
+pub struct Mbc3 {
+    ram_enable: bool,
+    rom_bank: u8,
+    ram_select: u8, // RAM bank (0x00-0x03) or RTC register (0x08-0x0C)
+
+    rom_count: usize,
+
+    pub rtc: Option<Rtc>,
+}
+
+impl RtcRegs {
+    fn read(&self, reg: u8) -> u8 {
+        match reg {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.day_low,
+            0x0C => self.day_high,
+            _ => 0xFF
+        }
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.day_low = value,
+            0x0C => self.day_high = value & (RTC_DAY_HIGH | RTC_HALT | RTC_CARRY),
+            _ => ()
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.day_high & RTC_HALT != 0
+    }
+
+    fn days(&self) -> u64 {
+        self.day_low as u64 | ((self.day_high & RTC_DAY_HIGH) as u64) << 8
+    }
+
+    // Advances the clock by a number of seconds, e.g. the time spent while the emulator was closed
+    pub fn add_seconds(&mut self, mut secs: u64) {
+        // Out of range values have to count up one by one until they wrap
+        while secs > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.add_second();
+            secs -= 1;
+        }
+
+        let total = secs + self.seconds as u64
+            + 60 * (self.minutes as u64 + 60 * (self.hours as u64 + 24 * self.days()));
+        let days = total / 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.day_low = days as u8;
+        self.day_high = (self.day_high & !RTC_DAY_HIGH) | ((days >> 8) & 1) as u8;
+        if days >= 512 {
+            self.day_high |= RTC_CARRY;
+        }
+    }
+
+    fn to_save(self, data: &mut Vec<u8>) {
+        for reg in [self.seconds, self.minutes, self.hours, self.day_low, self.day_high] {
+            data.extend((reg as u32).to_le_bytes());
+        }
+    }
+
+    fn from_save(data: &[u8]) -> Self {
+        let reg = |i: usize| data[i * 4];
+        RtcRegs {
+            seconds: reg(0) & 0x3F,
+            minutes: reg(1) & 0x3F,
+            hours: reg(2) & 0x1F,
+            day_low: reg(3),
+            day_high: reg(4) & (RTC_DAY_HIGH | RTC_HALT | RTC_CARRY),
+        }
+    }
+
+    // Advances the clock by one second.
+    // Out of range values count up to their bit width before wrapping, without carry.
+    pub fn add_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        let (day_low, overflow) = self.day_low.overflowing_add(1);
+        self.day_low = day_low;
+        if overflow {
+            if self.day_high & RTC_DAY_HIGH != 0 {
+                self.day_high = (self.day_high & !RTC_DAY_HIGH) | RTC_CARRY;
+            } else {
+                self.day_high |= RTC_DAY_HIGH;
+            }
+        }
+    }
+}
+
+impl Rtc {
+    // Should be ticked every M cycle
+    pub fn tick(&mut self) {
+        if self.regs.is_halted() {
+            return;
+        }
+
+        self.cycles += 1;
+        if self.cycles == RTC_CYCLES_PER_SECOND {
+            self.cycles = 0;
+            self.regs.add_second();
+        }
+    }
+
+    // Latching happens when 0x00 then 0x01 are written
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.regs;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    fn save_trailer(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_SAVE_LEN);
+        self.regs.to_save(&mut data);
+        self.latched.to_save(&mut data);
+        data.extend(unix_time().to_le_bytes());
+        data
+    }
+
+    fn load_trailer(&mut self, data: &[u8]) {
+        let timestamp = match data.len() {
+            RTC_SAVE_LEN => u64::from_le_bytes(data[40..48].try_into().unwrap()),
+            RTC_SAVE_LEN_32 => u32::from_le_bytes(data[40..44].try_into().unwrap()) as u64,
+            _ => {
+                warn!("Ignoring RTC save data of unexpected size: {}", data.len());
+                return;
+            }
+        };
+
+        self.regs = RtcRegs::from_save(&data[0..20]);
+        self.latched = RtcRegs::from_save(&data[20..40]);
+        if !self.regs.is_halted() {
+            self.regs.add_seconds(unix_time().saturating_sub(timestamp));
+        }
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        if reg == 0x08 {
+            // Writing the seconds resets the sub-second counter
+            self.cycles = 0;
+        }
+        self.regs.write(reg, value);
+    }
+}
+
+// The clock keeps the emulated time of the state, unlike save files
+impl SaveState for Mbc3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enable);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_select);
+
+        if let Some(rtc) = &self.rtc {
+            for regs in [&rtc.regs, &rtc.latched] {
+                for reg in [regs.seconds, regs.minutes, regs.hours, regs.day_low, regs.day_high] {
+                    w.u8(reg);
+                }
+            }
+            w.u32(rtc.cycles);
+            w.bool(rtc.latch_armed);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.ram_enable = r.bool()?;
+        self.rom_bank = r.u8()?;
+        self.ram_select = r.u8()?;
+
+        if let Some(rtc) = &mut self.rtc {
+            for regs in [&mut rtc.regs, &mut rtc.latched] {
+                for reg in [&mut regs.seconds, &mut regs.minutes, &mut regs.hours, &mut regs.day_low, &mut regs.day_high] {
+                    *reg = r.u8()?;
+                }
+            }
+            rtc.cycles = r.u32()?;
+            rtc.latch_armed = r.bool()?;
+        }
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..0x4000 => rom[addr as usize],
+            0x4000..0x8000 => {
+                let bank = self.rom_bank as usize % self.rom_count;
+                rom[bank * 0x4000 + (addr - 0x4000) as usize]
+            },
+            0xA000..0xC000 => {
+                if !self.ram_enable {
+                    return 0xFF;
+                }
+                match (self.ram_select, &self.rtc) {
+                    (0x00..0x08, _) if !ram.is_empty() => ram[self.ram_addr(ram, addr)],
+                    (0x08..=0x0C, Some(rtc)) => rtc.latched.read(self.ram_select),
+                    _ => 0xFF
+                }
+            },
+            _ => panic!("Should be unreachable. Addr: {addr:#06X}"),
+        }
+    }
+
+    fn write(&mut self, ram: &mut [u8], addr: u16, value: u8) {
+        match addr {
+            0x0000..0x2000 => self.ram_enable = value & 0xF == 0xA,
+            0x2000..0x4000 => {
+                self.rom_bank = value & 0x7F;
+                if self.rom_bank == 0 {self.rom_bank = 1};
+            },
+            0x4000..0x6000 => self.ram_select = value & 0x0F,
+            0x6000..0x8000 => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            },
+            0xA000..0xC000 => {
+                if !self.ram_enable {
+                    return;
+                }
+                match self.ram_select {
+                    0x00..0x08 if !ram.is_empty() => ram[self.ram_addr(ram, addr)] = value,
+                    0x08..=0x0C => {
+                        if let Some(rtc) = &mut self.rtc {
+                            rtc.write(self.ram_select, value);
+                        }
+                    },
+                    _ => ()
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn is_writeable(&self, _addr: u16) -> bool {
+        true
+    }
+
+    fn tick(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick();
+        }
+    }
+
+    fn save_trailer(&self) -> Vec<u8> {
+        self.rtc.map(|rtc| rtc.save_trailer()).unwrap_or_default()
+    }
+
+    fn load_trailer(&mut self, data: &[u8]) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_trailer(data);
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Mbc3 {
+    pub fn new(rom_count: usize, has_rtc: bool) -> Self {
+        Self {
+            ram_enable: false,
+            rom_bank: 1,
+            ram_select: 0,
+
+            rom_count,
+
+            rtc: if has_rtc {Some(Rtc::default())} else {None},
+        }
+    }
+
+    fn ram_addr(&self, ram: &[u8], addr: u16) -> usize {
+        (self.ram_select as usize * 0x2000 + (addr - 0xA000) as usize) % ram.len()
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/mbc3.rs"]
+mod mbc3_tests;
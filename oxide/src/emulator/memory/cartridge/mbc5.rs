@@ -0,0 +1,106 @@
+use super::*;
+
+/*
+ * MBC5 (header types 0x19-0x1E).
+ * 9 bits ROM bank number (up to 8 MiB) and 16 RAM banks.
+ * On rumble cartridges, bit 3 of the RAM bank register drives the motor
+ * and only 8 RAM banks are addressable.
+ */
+
+const RUMBLE_MOTOR: u8 = 0b1000;
+
+pub struct Mbc5 {
+    ram_enable: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    motor: bool,
+
+    rom_count: usize,
+    has_rumble: bool,
+}
+
+impl SaveState for Mbc5 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enable);
+        w.u16(self.rom_bank);
+        w.u8(self.ram_bank);
+        w.bool(self.motor);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.ram_enable = r.bool()?;
+        self.rom_bank = r.u16()?;
+        self.ram_bank = r.u8()?;
+        self.motor = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..0x4000 => rom[addr as usize],
+            0x4000..0x8000 => {
+                let bank = self.rom_bank as usize % self.rom_count;
+                rom[bank * 0x4000 + (addr - 0x4000) as usize]
+            },
+            0xA000..0xC000 => {
+                if !self.ram_enable || ram.is_empty() {
+                    return 0xFF;
+                }
+                ram[self.ram_addr(ram, addr)]
+            },
+            _ => panic!("Should be unreachable. Addr: {addr:#06X}"),
+        }
+    }
+
+    fn write(&mut self, ram: &mut [u8], addr: u16, value: u8) {
+        match addr {
+            0x0000..0x2000 => self.ram_enable = value & 0xF == 0xA,
+            0x2000..0x3000 => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..0x4000 => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 1) << 8),
+            0x4000..0x6000 => {
+                if self.has_rumble {
+                    self.motor = value & RUMBLE_MOTOR != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            },
+            0xA000..0xC000 if self.ram_enable && !ram.is_empty() => {
+                ram[self.ram_addr(ram, addr)] = value;
+            },
+            _ => ()
+        }
+    }
+
+    fn is_writeable(&self, _addr: u16) -> bool {
+        true
+    }
+
+    fn rumble(&self) -> bool {
+        self.motor
+    }
+}
+
+impl Mbc5 {
+    pub fn new(rom_count: usize, has_rumble: bool) -> Self {
+        Self {
+            ram_enable: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            motor: false,
+
+            rom_count,
+            has_rumble,
+        }
+    }
+
+    fn ram_addr(&self, ram: &[u8], addr: u16) -> usize {
+        (self.ram_bank as usize * 0x2000 + (addr - 0xA000) as usize) % ram.len()
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/mbc5.rs"]
+mod mbc5_tests;
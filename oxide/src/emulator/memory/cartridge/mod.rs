@@ -1,27 +1,56 @@
+pub mod header;
 pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
 mod no_mbc;
+mod save;
+mod state;
 
 use crate::emulator::memory::cartridge::no_mbc::NoMbc;
+use crate::emulator::state::{SaveState, StateReader, StateWriter};
+use header::*;
+use log::{info, warn};
 use mbc1::*;
+use mbc2::*;
+use mbc3::*;
+use mbc5::*;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub trait Mbc {
+// The state holds the MBC registers
+pub trait Mbc: SaveState {
     fn read(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8;
     fn write(&mut self, ram: &mut [u8], addr: u16, value: u8) -> ();
     
     fn is_writeable(&self, addr: u16) -> bool;
+
+    // Called every M cycle, for MBCs with their own clock
+    fn tick(&mut self) {}
+
+    // State of the rumble motor, for cartridges having one
+    fn rumble(&self) -> bool { false }
+
+    // Extra data appended to the RAM in save files, like the MBC3 RTC
+    fn save_trailer(&self) -> Vec<u8> { Vec::new() }
+    fn load_trailer(&mut self, _data: &[u8]) {}
 }
 
 pub struct Cartridge<M: Mbc> {
     mbc: M,
     rom: Vec<u8>,
     ram: Vec<u8>,
+    header: CartridgeHeader,
+    save_path: Option<PathBuf>, // Battery backed RAM file
+    dirty: bool,                // Was the RAM written since the last save ?
 }
 
 pub enum AnyCartridge {
     NoMbc(Cartridge<NoMbc>),
     MBC1(Cartridge<Mbc1>),
+    MBC2(Cartridge<Mbc2>),
+    MBC3(Cartridge<Mbc3>),
+    MBC5(Cartridge<Mbc5>),
 }
 
 impl<M: Mbc> Cartridge<M> {
@@ -29,11 +58,22 @@ impl<M: Mbc> Cartridge<M> {
         self.mbc.read(&self.rom, &self.ram, addr)
     }
 
+    fn new(mbc: M, rom: Vec<u8>, ram: Vec<u8>, header: CartridgeHeader) -> Self {
+        Cartridge { mbc, rom, ram, header, save_path: None, dirty: false }
+    }
+
     fn write(&mut self, addr: u16, value: u8) -> () {
         self.mbc.write(&mut self.ram, addr, value);
+        if (0xA000..0xC000).contains(&addr) {
+            self.dirty = true;
+        }
     }
     
     fn is_writeable(&self, addr: u16) -> bool { self.mbc.is_writeable(addr) }
+
+    fn tick(&mut self) { self.mbc.tick() }
+
+    fn rumble(&self) -> bool { self.mbc.rumble() }
 }
 
 impl AnyCartridge {
@@ -41,6 +81,9 @@ impl AnyCartridge {
         match self {
             AnyCartridge::NoMbc(cart) => cart.read(addr),
             AnyCartridge::MBC1(cart) => cart.read(addr),
+            AnyCartridge::MBC2(cart) => cart.read(addr),
+            AnyCartridge::MBC3(cart) => cart.read(addr),
+            AnyCartridge::MBC5(cart) => cart.read(addr),
         }
     }
     
@@ -48,39 +91,101 @@ impl AnyCartridge {
         match self {
             AnyCartridge::NoMbc(cart) => cart.write(addr, value),
             AnyCartridge::MBC1(cart) => cart.write(addr, value),
+            AnyCartridge::MBC2(cart) => cart.write(addr, value),
+            AnyCartridge::MBC3(cart) => cart.write(addr, value),
+            AnyCartridge::MBC5(cart) => cart.write(addr, value),
         }
     }
     
     pub fn is_writeable(&self, addr: u16) -> bool {
         match self {
             AnyCartridge::MBC1(cart) => cart.is_writeable(addr),
+            AnyCartridge::MBC2(cart) => cart.is_writeable(addr),
             AnyCartridge::NoMbc(cart) => cart.is_writeable(addr),
+            AnyCartridge::MBC3(cart) => cart.is_writeable(addr),
+            AnyCartridge::MBC5(cart) => cart.is_writeable(addr),
+        }
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        match self {
+            AnyCartridge::NoMbc(cart) => &cart.header,
+            AnyCartridge::MBC1(cart) => &cart.header,
+            AnyCartridge::MBC2(cart) => &cart.header,
+            AnyCartridge::MBC3(cart) => &cart.header,
+            AnyCartridge::MBC5(cart) => &cart.header,
+        }
+    }
+
+    // Should be ticked every M cycle
+    pub fn tick(&mut self) {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.tick(),
+            AnyCartridge::MBC1(cart) => cart.tick(),
+            AnyCartridge::MBC2(cart) => cart.tick(),
+            AnyCartridge::MBC3(cart) => cart.tick(),
+            AnyCartridge::MBC5(cart) => cart.tick(),
+        }
+    }
+
+    pub fn rumble(&self) -> bool {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.rumble(),
+            AnyCartridge::MBC1(cart) => cart.rumble(),
+            AnyCartridge::MBC2(cart) => cart.rumble(),
+            AnyCartridge::MBC3(cart) => cart.rumble(),
+            AnyCartridge::MBC5(cart) => cart.rumble(),
         }
     }
     
     pub fn load_from_file<P: AsRef<Path>>(rom_path: P) -> Result<Self, String> {
+        let rom_path = rom_path.as_ref();
         let rom = fs::read(rom_path).map_err(|e| e.to_string())?;
-        let mbc_val = rom[0x0147];
-        let ram : Vec<u8> = match rom[0x0149] {
-            0x00 => vec![0; 0],
-            0x01 => vec![0; 2 * 1024],
-            0x02 => vec![0; 8 * 1024],
-            0x03 => vec![0; 32 * 1024],
-            0x04 => vec![0; 128 * 1024],
-            0x05 => vec![0; 64 * 1024],
-            _ => return Err(format!("Invalid Ram size value: {}", rom[0x0149])),
-        };
-        
-        match mbc_val {
+        let mut cart = Self::from_rom(rom).map_err(|e| e.to_string())?;
+
+        if cart.header().has_battery() {
+            cart.load_save(rom_path.with_extension("sav"))?;
+        }
+        Ok(cart)
+    }
+
+    pub fn from_rom(rom: Vec<u8>) -> Result<Self, HeaderError> {
+        let header = CartridgeHeader::parse(&rom)?;
+        header.validate(&rom)?;
+        if !header.global_checksum_valid(&rom) {
+            warn!("Global checksum mismatch, the ROM may be corrupt");
+        }
+        if rom.len() > header.rom_size {
+            warn!("ROM is larger than declared in its header: {} bytes", rom.len());
+        }
+        info!("Loaded cartridge {} ({})", header.title, header.type_name());
+
+        let ram = vec![0; header.ram_size];
+        let rom_count = header.rom_size / 0x4000;
+        match header.cartridge_type {
             0x00 | 0x08 | 0x09 => {
                 let mbc = NoMbc{};
-                Ok(AnyCartridge::NoMbc(Cartridge { mbc, rom, ram }))
+                Ok(AnyCartridge::NoMbc(Cartridge::new(mbc, rom, ram, header)))
             },
             0x01 | 0x02 | 0x03 => {
-                let mbc = Mbc1::new(rom.len() / 0x4000, ram.len() / 0x2000);
-                Ok(AnyCartridge::MBC1(Cartridge {mbc, rom, ram}))
+                let mbc = Mbc1::new(rom_count, Mbc1::is_multicart(&rom));
+                Ok(AnyCartridge::MBC1(Cartridge::new(mbc, rom, ram, header)))
+            },
+            0x05 | 0x06 => {
+                // The header RAM size is 0, the RAM is inside the MBC
+                let mbc = Mbc2::new(rom_count);
+                let ram = vec![0; MBC2_RAM_SIZE];
+                Ok(AnyCartridge::MBC2(Cartridge::new(mbc, rom, ram, header)))
+            },
+            t @ 0x0F..=0x13 => {
+                let mbc = Mbc3::new(rom_count, t <= 0x10);
+                Ok(AnyCartridge::MBC3(Cartridge::new(mbc, rom, ram, header)))
+            },
+            t @ 0x19..=0x1E => {
+                let mbc = Mbc5::new(rom_count, t >= 0x1C);
+                Ok(AnyCartridge::MBC5(Cartridge::new(mbc, rom, ram, header)))
             },
-            _ => Err(format!("Unimplemented MBC Type: {mbc_val}"))
+            t => Err(HeaderError::UnsupportedType(t))
         }
     }
 }
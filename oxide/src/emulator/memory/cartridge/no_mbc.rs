@@ -2,6 +2,14 @@ use super::*;
 
 pub struct NoMbc {}
 
+impl SaveState for NoMbc {
+    fn save_state(&self, _w: &mut StateWriter) {}
+
+    fn load_state(&mut self, _r: &mut StateReader) -> Result<(), String> {
+        Ok(())
+    }
+}
+
 impl Mbc for NoMbc {
     fn read(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8 {
         match addr {
@@ -0,0 +1,76 @@
+use super::*;
+use log::info;
+
+/*
+ * Battery backed RAM persistence.
+ * Save files contain the raw external RAM, followed by the MBC trailer if any
+ * (the 48 bytes RTC block of MBC3 saves), as used by most other emulators.
+ */
+
+impl<M: Mbc> Cartridge<M> {
+    fn load_save(&mut self, path: PathBuf) -> Result<(), String> {
+        if path.exists() {
+            let data = fs::read(&path).map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+            let len = data.len().min(self.ram.len());
+            self.ram[..len].copy_from_slice(&data[..len]);
+            self.mbc.load_trailer(&data[len..]);
+            info!("Loaded save file {}", path.display());
+        }
+        self.save_path = Some(path);
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<(), String> {
+        let Some(path) = &self.save_path else {
+            return Ok(());
+        };
+
+        let mut data = self.ram.clone();
+        data.extend(self.mbc.save_trailer());
+        fs::write(path, data).map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn flush_save(&mut self) -> Result<(), String> {
+        if self.dirty {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl AnyCartridge {
+    pub(super) fn load_save(&mut self, path: PathBuf) -> Result<(), String> {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.load_save(path),
+            AnyCartridge::MBC1(cart) => cart.load_save(path),
+            AnyCartridge::MBC2(cart) => cart.load_save(path),
+            AnyCartridge::MBC3(cart) => cart.load_save(path),
+            AnyCartridge::MBC5(cart) => cart.load_save(path),
+        }
+    }
+
+    // Writes the save file of battery backed cartridges
+    pub fn save(&mut self) -> Result<(), String> {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.save(),
+            AnyCartridge::MBC1(cart) => cart.save(),
+            AnyCartridge::MBC2(cart) => cart.save(),
+            AnyCartridge::MBC3(cart) => cart.save(),
+            AnyCartridge::MBC5(cart) => cart.save(),
+        }
+    }
+
+    // Writes the save file only if the RAM changed since the last save
+    pub fn flush_save(&mut self) -> Result<(), String> {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.flush_save(),
+            AnyCartridge::MBC1(cart) => cart.flush_save(),
+            AnyCartridge::MBC2(cart) => cart.flush_save(),
+            AnyCartridge::MBC3(cart) => cart.flush_save(),
+            AnyCartridge::MBC5(cart) => cart.flush_save(),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use super::*;
+use crate::emulator::state::*;
+
+impl<M: Mbc> SaveState for Cartridge<M> {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        self.mbc.save_state(w);
+    }
+
+    // The RAM is written to the save file on the next flush
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.ram)?;
+        self.dirty = true;
+        self.mbc.load_state(r)
+    }
+}
+
+impl SaveState for AnyCartridge {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.save_state(w),
+            AnyCartridge::MBC1(cart) => cart.save_state(w),
+            AnyCartridge::MBC2(cart) => cart.save_state(w),
+            AnyCartridge::MBC3(cart) => cart.save_state(w),
+            AnyCartridge::MBC5(cart) => cart.save_state(w),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        match self {
+            AnyCartridge::NoMbc(cart) => cart.load_state(r),
+            AnyCartridge::MBC1(cart) => cart.load_state(r),
+            AnyCartridge::MBC2(cart) => cart.load_state(r),
+            AnyCartridge::MBC3(cart) => cart.load_state(r),
+            AnyCartridge::MBC5(cart) => cart.load_state(r),
+        }
+    }
+}
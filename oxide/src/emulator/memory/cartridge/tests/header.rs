@@ -0,0 +1,89 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::cartridge::header::*;
+
+    fn make_rom(title: &[u8], cartridge_type: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom[0x147] = cartridge_type;
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+        let global = CartridgeHeader::compute_global_checksum(&rom);
+        rom[0x14E..0x150].copy_from_slice(&global.to_be_bytes());
+        rom
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut rom = make_rom(b"TETRIS", 0x03);
+        rom[0x149] = 0x02;
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        rom[0x14C] = 1;
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "TETRIS", "Title");
+        assert_eq!(header.licensee(), "01", "New licensee code");
+        assert_eq!(header.type_name(), "MBC1+RAM+BATTERY", "Type");
+        assert_eq!(header.rom_size, 0x8000, "ROM size");
+        assert_eq!(header.ram_size, 0x2000, "RAM size");
+        assert_eq!(header.version, 1, "Version");
+        assert!(header.has_battery(), "Battery");
+        assert_eq!(header.validate(&rom), Ok(()), "Valid header");
+        assert!(!header.global_checksum_valid(&rom), "Global checksum not updated");
+    }
+
+    #[test]
+    fn test_cgb_title() {
+        let mut rom = make_rom(b"POKEMON_SLVAAXE", 0x10);
+        rom[0x143] = 0x80;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON_SLV", "Title without manufacturer code");
+        assert_eq!(header.manufacturer, "AAXE", "Manufacturer code");
+        assert!(header.is_cgb(), "CGB flag");
+        assert!(!header.is_sgb(), "SGB flag");
+    }
+
+    #[test]
+    fn test_title_checksum() {
+        let mut rom = make_rom(b"POKEMON RED", 0x13);
+        rom[0x14B] = 0x01;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title_checksum(), 0x14, "Title checksum");
+        assert!(header.is_nintendo(), "Old licensee code");
+
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"08");
+        assert!(!CartridgeHeader::parse(&rom).unwrap().is_nintendo(), "New licensee code");
+    }
+
+    #[test]
+    fn test_errors() {
+        let rom = make_rom(b"TEST", 0x00);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(header.global_checksum_valid(&rom), "Global checksum");
+
+        assert_eq!(CartridgeHeader::parse(&rom[..0x100]), Err(HeaderError::Truncated(0x100)), "Truncated header");
+        assert_eq!(CartridgeHeader::parse(&rom[..0x4000]),
+            Err(HeaderError::RomSizeMismatch { expected: 0x8000, actual: 0x4000 }), "Truncated ROM");
+
+        let mut bad = rom.clone();
+        bad[0x148] = 0x20;
+        assert_eq!(CartridgeHeader::parse(&bad), Err(HeaderError::InvalidRomSize(0x20)), "ROM size");
+        bad[0x148] = 0x00;
+        bad[0x149] = 0x06;
+        assert_eq!(CartridgeHeader::parse(&bad), Err(HeaderError::InvalidRamSize(0x06)), "RAM size");
+
+        let mut bad = rom.clone();
+        bad[0x110] ^= 0xFF;
+        assert_eq!(header.validate(&bad), Err(HeaderError::BadLogo), "Logo");
+
+        let mut bad = rom.clone();
+        bad[0x134] = b'X';
+        let actual = CartridgeHeader::compute_header_checksum(&bad);
+        assert_eq!(header.validate(&bad),
+            Err(HeaderError::BadHeaderChecksum { expected: header.header_checksum, actual }), "Header checksum");
+    }
+}
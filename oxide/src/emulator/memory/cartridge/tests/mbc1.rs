@@ -0,0 +1,265 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::cartridge::header::NINTENDO_LOGO;
+    use crate::emulator::memory::cartridge::mbc1::*;
+    use crate::emulator::memory::cartridge::Mbc;
+
+    // ROM with the bank number at the start of each bank
+    fn make_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * 0x4000];
+        for bank in 0..banks {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        rom
+    }
+
+    fn make_multicart() -> Vec<u8> {
+        let mut rom = make_rom(64);
+        for game in 0..4 {
+            let logo = game * 0x10 * 0x4000 + 0x104;
+            rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        }
+        rom
+    }
+
+    fn banks(mbc: &Mbc1, rom: &[u8]) -> (u8, u8) {
+        (mbc.read(rom, &[], 0x0000), mbc.read(rom, &[], 0x4000))
+    }
+
+    #[test]
+    fn test_multicart_detection() {
+        assert!(Mbc1::is_multicart(&make_multicart()), "Logo in bank 0x10");
+
+        let mut rom = make_multicart();
+        rom[0x10 * 0x4000 + 0x104] = 0;
+        assert!(!Mbc1::is_multicart(&rom), "No logo in bank 0x10");
+
+        let mut rom = make_rom(128);
+        let logo = 0x10 * 0x4000 + 0x104;
+        rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        assert!(!Mbc1::is_multicart(&rom), "Multicarts are 1 MiB");
+    }
+
+    #[test]
+    fn test_multicart_banking() {
+        let rom = make_multicart();
+        let mut mbc = Mbc1::new(64, true);
+        let mut ram = vec![];
+
+        mbc.write(&mut ram, 0x2000, 0x1F);
+        assert_eq!(banks(&mbc, &rom), (0x00, 0x0F), "Bit 4 of the ROM bank is not wired");
+        mbc.write(&mut ram, 0x2000, 0x10);
+        assert_eq!(banks(&mbc, &rom), (0x00, 0x00), "Zero check uses 5 bits");
+
+        mbc.write(&mut ram, 0x4000, 0x02);
+        mbc.write(&mut ram, 0x2000, 0x03);
+        assert_eq!(banks(&mbc, &rom), (0x00, 0x23), "Upper bits select the game");
+
+        mbc.write(&mut ram, 0x6000, 0x01);
+        assert_eq!(banks(&mbc, &rom), (0x20, 0x23), "Mode 1 maps the game start at 0x0000");
+        mbc.write(&mut ram, 0x4000, 0x03);
+        assert_eq!(banks(&mbc, &rom), (0x30, 0x33), "Last game");
+    }
+
+    #[test]
+    fn test_standard_banking() {
+        let rom = make_rom(64);
+        let mut mbc = Mbc1::new(64, false);
+        let mut ram = vec![];
+
+        mbc.write(&mut ram, 0x2000, 0x1F);
+        assert_eq!(banks(&mbc, &rom), (0x00, 0x1F), "5 bits ROM bank");
+        mbc.write(&mut ram, 0x4000, 0x01);
+        assert_eq!(banks(&mbc, &rom), (0x00, 0x3F), "Upper bits");
+        mbc.write(&mut ram, 0x6000, 0x01);
+        assert_eq!(banks(&mbc, &rom), (0x20, 0x3F), "Mode 1 bank 0");
+    }
+
+    /* Tests modeled on mooneye-test-suite emulator-only/mbc1 ROMs */
+
+    #[test]
+    fn test_bits_bank1() {
+        let rom = make_rom(32);
+        let mut mbc = Mbc1::new(32, false);
+        let mut ram = vec![];
+
+        for addr in [0x2000, 0x2FFF, 0x3000, 0x3FFF] {
+            for value in 0..=0xFFu8 {
+                mbc.write(&mut ram, addr, value);
+                let expected = if value & 0x1F == 0 {1} else {value & 0x1F};
+                assert_eq!(banks(&mbc, &rom).1, expected, "BANK1 write {value:#04X} at {addr:#06X}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bits_bank2() {
+        let rom = make_rom(128);
+        let mut mbc = Mbc1::new(128, false);
+        let mut ram = vec![];
+
+        for addr in [0x4000, 0x4FFF, 0x5000, 0x5FFF] {
+            for value in 0..=0xFFu8 {
+                mbc.write(&mut ram, addr, value);
+                let expected = ((value & 0b11) << 5) | 1;
+                assert_eq!(banks(&mbc, &rom), (0, expected), "BANK2 write {value:#04X} at {addr:#06X}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bits_mode() {
+        let rom = make_rom(128);
+        let mut mbc = Mbc1::new(128, false);
+        let mut ram = vec![];
+        mbc.write(&mut ram, 0x4000, 0x02);
+
+        for addr in [0x6000, 0x6FFF, 0x7000, 0x7FFF] {
+            for value in 0..=0xFFu8 {
+                mbc.write(&mut ram, addr, value);
+                let expected = if value & 1 != 0 {0x40} else {0x00};
+                assert_eq!(banks(&mbc, &rom).0, expected, "MODE write {value:#04X} at {addr:#06X}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bits_ramg() {
+        let mut mbc = Mbc1::new(2, false);
+        let mut ram = vec![0; 0x2000];
+
+        for addr in [0x0000, 0x0FFF, 0x1000, 0x1FFF] {
+            for value in 0..=0xFFu8 {
+                mbc.write(&mut ram, 0x0000, 0x0A);
+                mbc.write(&mut ram, 0xA000, 0x00);
+                mbc.write(&mut ram, addr, value);
+                mbc.write(&mut ram, 0xA000, 0x5A);
+
+                let enabled = value & 0x0F == 0x0A;
+                let expected = if enabled {0x5A} else {0xFF};
+                assert_eq!(mbc.read(&[], &ram, 0xA000), expected, "RAMG write {value:#04X} at {addr:#06X}");
+                assert_eq!(ram[0], if enabled {0x5A} else {0x00}, "RAM write with RAMG {value:#04X}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_ram_64kb() {
+        let mut mbc = Mbc1::new(2, false);
+        let mut ram = vec![0; 0x2000];
+        mbc.write(&mut ram, 0x0000, 0x0A);
+        mbc.write(&mut ram, 0x6000, 0x01);
+
+        for bank in 0..4u8 {
+            mbc.write(&mut ram, 0x4000, bank);
+            mbc.write(&mut ram, 0xA000 + bank as u16, bank + 1);
+        }
+        for bank in 0..4u8 {
+            mbc.write(&mut ram, 0x4000, bank);
+            for i in 0..4u8 {
+                assert_eq!(mbc.read(&[], &ram, 0xA000 + i as u16), i + 1, "Single bank, BANK2 {bank}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_ram_256kb() {
+        let mut mbc = Mbc1::new(2, false);
+        let mut ram = vec![0; 0x8000];
+        mbc.write(&mut ram, 0x0000, 0x0A);
+
+        // Mode 1: BANK2 selects the RAM bank
+        mbc.write(&mut ram, 0x6000, 0x01);
+        for bank in 0..4u8 {
+            mbc.write(&mut ram, 0x4000, bank);
+            mbc.write(&mut ram, 0xB000, 0x10 + bank);
+        }
+        for bank in 0..4u8 {
+            mbc.write(&mut ram, 0x4000, bank);
+            assert_eq!(mbc.read(&[], &ram, 0xB000), 0x10 + bank, "Mode 1 RAM bank {bank}");
+        }
+
+        // Mode 0: always bank 0
+        mbc.write(&mut ram, 0x6000, 0x00);
+        for bank in 0..4u8 {
+            mbc.write(&mut ram, 0x4000, bank);
+            assert_eq!(mbc.read(&[], &ram, 0xB000), 0x10, "Mode 0 with BANK2 {bank}");
+        }
+
+        mbc.write(&mut ram, 0x0000, 0x00);
+        assert_eq!(mbc.read(&[], &ram, 0xB000), 0xFF, "Disabled RAM");
+        mbc.write(&mut ram, 0xB000, 0x42);
+        assert_eq!(ram[0x1000], 0x10, "Write to disabled RAM");
+    }
+
+    #[test]
+    fn test_no_ram() {
+        let mut mbc = Mbc1::new(2, false);
+        let mut ram = vec![];
+        mbc.write(&mut ram, 0x0000, 0x0A);
+        mbc.write(&mut ram, 0xA000, 0x42);
+        assert_eq!(mbc.read(&[], &ram, 0xA000), 0xFF, "No RAM");
+    }
+
+    // Checks every BANK1/BANK2/MODE combination against the expected mapping
+    fn check_rom_size(banks_count: usize) {
+        let rom = make_rom(banks_count);
+        let mut mbc = Mbc1::new(banks_count, false);
+        let mut ram = vec![];
+
+        for mode in 0..2u8 {
+            mbc.write(&mut ram, 0x6000, mode);
+            for bank2 in 0..4u8 {
+                mbc.write(&mut ram, 0x4000, bank2);
+                for bank1 in 0..0x20u8 {
+                    mbc.write(&mut ram, 0x2000, bank1);
+                    let low = if bank1 == 0 {1} else {bank1} as usize;
+                    let upper = (bank2 as usize) << 5;
+                    let bank0 = if mode == 1 {upper % banks_count} else {0};
+                    let bankx = (upper | low) % banks_count;
+                    assert_eq!(banks(&mbc, &rom), (bank0 as u8, bankx as u8),
+                        "{} KiB ROM, mode {mode}, BANK2 {bank2}, BANK1 {bank1:#04X}", banks_count * 16);
+                }
+            }
+        }
+    }
+
+    // The mooneye ROM names are sizes in bits
+    #[test]
+    fn test_rom_512kb() {
+        check_rom_size(4);
+    }
+
+    #[test]
+    fn test_rom_1mb() {
+        check_rom_size(8);
+    }
+
+    #[test]
+    fn test_rom_2mb() {
+        check_rom_size(16);
+    }
+
+    #[test]
+    fn test_rom_4mb() {
+        check_rom_size(32);
+    }
+
+    #[test]
+    fn test_rom_8mb() {
+        check_rom_size(64);
+    }
+
+    #[test]
+    fn test_rom_16mb() {
+        check_rom_size(128);
+        // Bank 0x20, 0x40 and 0x60 cannot be mapped to 0x4000-0x7FFF
+        let rom = make_rom(128);
+        let mut mbc = Mbc1::new(128, false);
+        let mut ram = vec![];
+        mbc.write(&mut ram, 0x4000, 0x01);
+        mbc.write(&mut ram, 0x2000, 0x00);
+        assert_eq!(banks(&mbc, &rom), (0x00, 0x21), "Bank 0x20 maps to 0x21");
+    }
+}
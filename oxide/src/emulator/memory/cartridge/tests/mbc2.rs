@@ -0,0 +1,39 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::cartridge::mbc2::*;
+    use crate::emulator::memory::cartridge::Mbc;
+
+    #[test]
+    fn test_register_select() {
+        let mbc_rom: Vec<u8> = (0..16).flat_map(|b| vec![b as u8; 0x4000]).collect();
+        let mut mbc = Mbc2::new(16);
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+
+        mbc.write(&mut ram, 0x2100, 0x05);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x4000), 5, "A8 set selects the ROM bank");
+        mbc.write(&mut ram, 0x0100, 0x0A);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x4000), 10, "ROM bank anywhere in 0x0000-0x3FFF");
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0xA000), 0xFF, "RAM still disabled");
+
+        mbc.write(&mut ram, 0x3E00, 0x0A);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0xA000), 0xF0, "A8 clear enables the RAM");
+        mbc.write(&mut ram, 0x2100, 0x00);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x4000), 1, "Bank 0 maps to 1");
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut mbc = Mbc2::new(2);
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+        mbc.write(&mut ram, 0x0000, 0x0A);
+
+        mbc.write(&mut ram, 0xA010, 0xA5);
+        assert_eq!(mbc.read(&[], &ram, 0xA010), 0xF5, "Upper nibble reads as 1");
+        assert_eq!(mbc.read(&[], &ram, 0xA210), 0xF5, "RAM echo");
+        assert_eq!(mbc.read(&[], &ram, 0xBE10), 0xF5, "RAM echo up to 0xBFFF");
+
+        mbc.write(&mut ram, 0xBFFF, 0x03);
+        assert_eq!(mbc.read(&[], &ram, 0xA1FF), 0xF3, "Write through the echo");
+    }
+}
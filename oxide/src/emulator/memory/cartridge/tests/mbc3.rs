@@ -0,0 +1,156 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::cartridge::mbc3::*;
+    use crate::emulator::memory::cartridge::Mbc;
+
+    fn rtc_mbc() -> (Mbc3, Vec<u8>) {
+        let mut mbc = Mbc3::new(8, true);
+        let mut ram = vec![0; 0x8000];
+        mbc.write(&mut ram, 0x0000, 0x0A); // Enable RAM and RTC
+        (mbc, ram)
+    }
+
+    fn write_rtc(mbc: &mut Mbc3, ram: &mut [u8], reg: u8, value: u8) {
+        mbc.write(ram, 0x4000, reg);
+        mbc.write(ram, 0xA000, value);
+    }
+
+    fn latch(mbc: &mut Mbc3, ram: &mut [u8]) {
+        mbc.write(ram, 0x6000, 0x00);
+        mbc.write(ram, 0x6000, 0x01);
+    }
+
+    fn read_rtc(mbc: &mut Mbc3, ram: &mut [u8], reg: u8) -> u8 {
+        mbc.write(ram, 0x4000, reg);
+        mbc.read(&[], ram, 0xA000)
+    }
+
+    #[test]
+    fn test_rom_banking() {
+        let mbc_rom: Vec<u8> = (0..8).flat_map(|b| vec![b as u8; 0x4000]).collect();
+        let mut mbc = Mbc3::new(8, false);
+        let mut ram = vec![];
+
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x4000), 1, "Default bank");
+        mbc.write(&mut ram, 0x2000, 0);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x4000), 1, "Bank 0 maps to 1");
+        mbc.write(&mut ram, 0x2000, 5);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x7FFF), 5, "Bank 5");
+        mbc.write(&mut ram, 0x2000, 0x0E);
+        assert_eq!(mbc.read(&mbc_rom, &ram, 0x4000), 6, "Bank wraps on ROM size");
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let (mut mbc, mut ram) = rtc_mbc();
+
+        for bank in 0..4 {
+            mbc.write(&mut ram, 0x4000, bank);
+            mbc.write(&mut ram, 0xA123, 0x10 + bank);
+        }
+        for bank in 0..4 {
+            mbc.write(&mut ram, 0x4000, bank);
+            assert_eq!(mbc.read(&[], &ram, 0xA123), 0x10 + bank, "RAM bank {bank}");
+        }
+
+        mbc.write(&mut ram, 0x0000, 0x00);
+        assert_eq!(mbc.read(&[], &ram, 0xA123), 0xFF, "Disabled RAM");
+    }
+
+    #[test]
+    fn test_rtc_latch() {
+        let (mut mbc, mut ram) = rtc_mbc();
+        write_rtc(&mut mbc, &mut ram, 0x08, 10);
+        latch(&mut mbc, &mut ram);
+
+        for _ in 0..RTC_CYCLES_PER_SECOND {
+            mbc.tick();
+        }
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x08), 10, "Latched value does not change");
+
+        mbc.write(&mut ram, 0x6000, 0x01);
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x08), 10, "Latch needs 0x00 then 0x01");
+
+        latch(&mut mbc, &mut ram);
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x08), 11, "Seconds after latch");
+    }
+
+    #[test]
+    fn test_rtc_rollover() {
+        let (mut mbc, mut ram) = rtc_mbc();
+        write_rtc(&mut mbc, &mut ram, 0x08, 59);
+        write_rtc(&mut mbc, &mut ram, 0x09, 59);
+        write_rtc(&mut mbc, &mut ram, 0x0A, 23);
+        write_rtc(&mut mbc, &mut ram, 0x0B, 0xFF);
+        write_rtc(&mut mbc, &mut ram, 0x0C, 0x01);
+
+        for _ in 0..RTC_CYCLES_PER_SECOND {
+            mbc.tick();
+        }
+        latch(&mut mbc, &mut ram);
+
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x08), 0, "Seconds");
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x09), 0, "Minutes");
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x0A), 0, "Hours");
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x0B), 0, "Days low");
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x0C), 0x80, "Day carry");
+    }
+
+    #[test]
+    fn test_rtc_halt() {
+        let (mut mbc, mut ram) = rtc_mbc();
+        write_rtc(&mut mbc, &mut ram, 0x0C, 0x40);
+        write_rtc(&mut mbc, &mut ram, 0x08, 63);
+
+        for _ in 0..RTC_CYCLES_PER_SECOND {
+            mbc.tick();
+        }
+        latch(&mut mbc, &mut ram);
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x08), 63, "Halted clock");
+
+        write_rtc(&mut mbc, &mut ram, 0x0C, 0x00);
+        for _ in 0..RTC_CYCLES_PER_SECOND {
+            mbc.tick();
+        }
+        latch(&mut mbc, &mut ram);
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x08), 0, "Invalid seconds wrap without carry");
+        assert_eq!(read_rtc(&mut mbc, &mut ram, 0x09), 0, "No minute carry");
+    }
+
+    #[test]
+    fn test_add_seconds() {
+        let mut regs = RtcRegs { seconds: 30, minutes: 59, hours: 23, day_low: 0xFF, day_high: 0x01 };
+        regs.add_seconds(30);
+        assert_eq!(regs, RtcRegs { seconds: 0, minutes: 0, hours: 0, day_low: 0, day_high: 0x80 }, "Day counter overflow");
+
+        let mut regs = RtcRegs { seconds: 62, ..Default::default() };
+        regs.add_seconds(2 + 3600 * 25);
+        assert_eq!(regs, RtcRegs { seconds: 0, minutes: 0, hours: 1, day_low: 1, day_high: 0 }, "Invalid seconds");
+    }
+
+    #[test]
+    fn test_rtc_save() {
+        let (mut mbc, mut ram) = rtc_mbc();
+        write_rtc(&mut mbc, &mut ram, 0x08, 5);
+        write_rtc(&mut mbc, &mut ram, 0x0B, 0x20);
+        latch(&mut mbc, &mut ram);
+
+        let mut trailer = mbc.save_trailer();
+        assert_eq!(trailer.len(), 48, "RTC block size");
+        assert_eq!(&trailer[0..4], &[5, 0, 0, 0], "Seconds as 32 bits value");
+
+        // Saved one hour, one minute and one second ago
+        let timestamp = u64::from_le_bytes(trailer[40..48].try_into().unwrap()) - 3661;
+        trailer[40..48].copy_from_slice(&timestamp.to_le_bytes());
+
+        let (mut loaded, mut ram) = rtc_mbc();
+        loaded.load_trailer(&trailer);
+        assert_eq!(read_rtc(&mut loaded, &mut ram, 0x08), 5, "Latched registers restored");
+        latch(&mut loaded, &mut ram);
+        assert_eq!(read_rtc(&mut loaded, &mut ram, 0x08), 6, "Seconds caught up");
+        assert_eq!(read_rtc(&mut loaded, &mut ram, 0x09), 1, "Minutes caught up");
+        assert_eq!(read_rtc(&mut loaded, &mut ram, 0x0A), 1, "Hours caught up");
+        assert_eq!(read_rtc(&mut loaded, &mut ram, 0x0B), 0x20, "Days restored");
+    }
+}
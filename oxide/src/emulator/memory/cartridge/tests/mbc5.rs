@@ -0,0 +1,62 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::cartridge::mbc5::*;
+    use crate::emulator::memory::cartridge::Mbc;
+
+    #[test]
+    fn test_rom_banking() {
+        // Bank number stored in the first two bytes of each bank
+        let mut mbc_rom = vec![0; 512 * 0x4000];
+        for bank in 0..512 {
+            mbc_rom[bank * 0x4000] = bank as u8;
+            mbc_rom[bank * 0x4000 + 1] = (bank >> 8) as u8;
+        }
+        let mut mbc = Mbc5::new(512, false);
+        let mut ram = vec![];
+        let bank = |mbc: &Mbc5| {
+            mbc.read(&mbc_rom, &[], 0x4000) as u16 | (mbc.read(&mbc_rom, &[], 0x4001) as u16) << 8
+        };
+
+        assert_eq!(bank(&mbc), 1, "Default bank");
+        mbc.write(&mut ram, 0x2000, 0);
+        assert_eq!(bank(&mbc), 0, "Bank 0 is selectable");
+        mbc.write(&mut ram, 0x2000, 0x23);
+        mbc.write(&mut ram, 0x3000, 1);
+        assert_eq!(bank(&mbc), 0x123, "9 bits bank");
+        mbc.write(&mut ram, 0x2000, 0xFF);
+        assert_eq!(bank(&mbc), 0x1FF, "Low byte keeps bit 8");
+        mbc.write(&mut ram, 0x3000, 0);
+        assert_eq!(bank(&mbc), 0x0FF, "High bit cleared");
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = Mbc5::new(2, false);
+        let mut ram = vec![0; 16 * 0x2000];
+        mbc.write(&mut ram, 0x0000, 0x0A);
+
+        for bank in 0..16 {
+            mbc.write(&mut ram, 0x4000, bank);
+            mbc.write(&mut ram, 0xB000, bank * 3);
+        }
+        for bank in 0..16 {
+            mbc.write(&mut ram, 0x4000, bank);
+            assert_eq!(mbc.read(&[], &ram, 0xB000), bank * 3, "RAM bank {bank}");
+        }
+    }
+
+    #[test]
+    fn test_rumble() {
+        let mut mbc = Mbc5::new(2, true);
+        let mut ram = vec![0; 8 * 0x2000];
+        mbc.write(&mut ram, 0x0000, 0x0A);
+
+        mbc.write(&mut ram, 0x4000, 0x0B);
+        assert!(mbc.rumble(), "Motor on");
+        mbc.write(&mut ram, 0xA000, 0x42);
+        mbc.write(&mut ram, 0x4000, 0x03);
+        assert!(!mbc.rumble(), "Motor off");
+        assert_eq!(mbc.read(&[], &ram, 0xA000), 0x42, "Motor bit does not select RAM bank");
+    }
+}
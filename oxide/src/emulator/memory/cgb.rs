@@ -0,0 +1,129 @@
+use super::*;
+use crate::emulator::memory::regdefines::*;
+use crate::emulator::ppu::compat;
+
+/*
+ * CGB only registers.
+ * VBK (0xFF4F) selects the VRAM bank, SVBK (0xFF70) the WRAM bank mapped at
+ * 0xD000-0xDFFF. KEY1 (0xFF4D) arms a speed switch, which is performed by
+ * the next STOP instruction. In double speed mode, the CPU, timer and DMA run
+ * twice as fast, while the PPU and APU keep the same speed.
+ * BCPS/BCPD (0xFF68-0xFF69) and OCPS/OCPD (0xFF6A-0xFF6B) access the BG and
+ * OBJ palette RAM, which is not accessible during Mode 3. OPRI (0xFF6C)
+ * selects the object priority mode.
+ * These registers read 0xFF and ignore writes on DMG.
+ *
+ * KEY0 (0xFF4C) can only be written by the boot ROM. Setting bit 2 switches
+ * to DMG compatibility mode, where the CGB registers are locked and the DMG
+ * palettes index the colors loaded by the boot ROM. Without boot ROM, this
+ * is done when the bus is created, and the palettes can be changed with a
+ * button combination during the first frames, like during the boot logo.
+ */
+
+const KEY0_DMG_MODE: u8 = 0b0000_0100;
+
+// Frames during which the palettes can be overridden without boot ROM
+const COMBO_FRAMES: u8 = 120;
+
+impl Bus {
+    // Are the CGB features available to the running program ?
+    pub fn is_cgb(&self) -> bool {
+        self.model == Model::Cgb && !self.dmg_compat()
+    }
+
+    pub fn dmg_compat(&self) -> bool {
+        self.model == Model::Cgb && self.ioregs[0x4C] & KEY0_DMG_MODE != 0
+    }
+
+    // Does what the CGB boot ROM would do for a DMG cartridge
+    pub(super) fn init_dmg_compat(&mut self) {
+        let combination = compat::title_combination(self.cartridge.header());
+        info!("DMG compatibility mode, using palette combination {combination}");
+        self.ioregs[0x4C] = KEY0_DMG_MODE;
+        self.ioregs[0x6C] = 1;
+        self.palettes.load_combination(combination);
+        self.compat_combo_frames = COMBO_FRAMES;
+    }
+
+    // Called on each VBlank
+    pub fn poll_compat_combo(&mut self) {
+        if self.compat_combo_frames == 0 {
+            return;
+        }
+        self.compat_combo_frames -= 1;
+
+        if let Some(combination) = compat::key_combination(self.io_manager.get_joystate()) {
+            info!("Palette combination {combination} selected");
+            self.palettes.load_combination(combination);
+        }
+    }
+
+    pub(super) fn read_cgb_regs(&self, addr: u16) -> u8 {
+        if !self.is_cgb() {
+            return 0xFF;
+        }
+
+        match addr {
+            KEY1 => 0x7E | (self.double_speed as u8) << 7 | self.ioregs[0x4D] & 1,
+            VBK => 0xFE | self.ram.vram_bank() as u8,
+            SVBK => 0xF8 | self.ioregs[0x70] & 0b111,
+            BCPS => self.palettes.bg.read_spec(),
+            OCPS => self.palettes.obj.read_spec(),
+            BCPD if !self.palette_locked() => self.palettes.bg.read_data(),
+            OCPD if !self.palette_locked() => self.palettes.obj.read_data(),
+            OPRI => 0xFE | self.ioregs[0x6C] & 1,
+            _ => 0xFF,
+        }
+    }
+
+    pub(super) fn write_cgb_regs(&mut self, addr: u16, value: u8) {
+        if !self.is_cgb() {
+            return;
+        }
+
+        match addr {
+            KEY0 if self.boot_enabled => self.ioregs[0x4C] = value,
+            KEY1 => self.ioregs[0x4D] = value & 1,
+            VBK => self.ram.set_vram_bank((value & 1) as usize),
+            SVBK => {
+                self.ioregs[0x70] = value & 0b111;
+                self.ram.set_wram_bank((value & 0b111) as usize);
+            },
+            BCPS => self.palettes.bg.write_spec(value),
+            OCPS => self.palettes.obj.write_spec(value),
+            BCPD => {
+                let blocked = self.palette_locked();
+                self.palettes.bg.write_data(value, blocked);
+            },
+            OCPD => {
+                let blocked = self.palette_locked();
+                self.palettes.obj.write_data(value, blocked);
+            },
+            OPRI => self.ioregs[0x6C] = value & 1,
+            _ => (),
+        }
+    }
+
+    // The PPU reads the palettes during Mode 3
+    fn palette_locked(&self) -> bool {
+        self.get_ppu_mode() == Mode::Mode3
+    }
+
+    // Objects are prioritized by OAM index instead of X coordinate
+    pub fn oam_priority(&self) -> bool {
+        self.is_cgb() && self.ioregs[0x6C] & 1 == 0
+    }
+
+    // Called by STOP. Returns false if no speed switch was armed
+    pub fn try_speed_switch(&mut self) -> bool {
+        if !self.is_cgb() || self.ioregs[0x4D] & 1 == 0 {
+            return false;
+        }
+
+        self.double_speed = !self.double_speed;
+        self.ioregs[0x4D] = 0;
+        self.div_written = true;
+        info!("Switched to {} speed mode", if self.double_speed {"double"} else {"normal"});
+        true
+    }
+}
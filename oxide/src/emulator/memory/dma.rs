@@ -0,0 +1,130 @@
+use super::*;
+use crate::emulator::state::*;
+
+/*
+ * OAM DMA (0xFF46).
+ * Writing XX to the register copies XX00-XX9F to OAM, one byte per M-cycle,
+ * after a 1 M-cycle startup delay. While the transfer runs, OAM is not
+ * readable by the CPU, and the bus used as source returns the byte being
+ * transferred. HRAM and IO registers stay accessible.
+ */
+
+pub const OAM_DMA_LEN: u16 = 0xA0;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct OamDma {
+    source: u16,  // Base address of the running transfer
+    index: u16,   // Next byte to copy
+    active: bool, // Is a transfer running ?
+    value: u8,    // Last byte put on the source bus
+    pending: Option<(u16, u8)>, // Requested transfer (source, startup delay)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum DmaBus {
+    External, // ROM, external RAM and WRAM
+    Video,    // VRAM
+}
+
+impl DmaBus {
+    fn from_addr(addr: u16) -> Option<Self> {
+        match addr {
+            0x0000..0x8000 | 0xA000..0xFE00 => Some(DmaBus::External),
+            0x8000..0xA000 => Some(DmaBus::Video),
+            _ => None,
+        }
+    }
+}
+
+impl OamDma {
+    pub fn start(&mut self, value: u8) {
+        self.pending = Some(((value as u16) << 8, 1));
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+impl SaveState for OamDma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.source);
+        w.u16(self.index);
+        w.bool(self.active);
+        w.u8(self.value);
+        w.bool(self.pending.is_some());
+        let (source, delay) = self.pending.unwrap_or_default();
+        w.u16(source);
+        w.u8(delay);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.source = r.u16()?;
+        self.index = r.u16()?;
+        self.active = r.bool()?;
+        self.value = r.u8()?;
+        let pending = r.bool()?;
+        let transfer = (r.u16()?, r.u8()?);
+        self.pending = pending.then_some(transfer);
+        Ok(())
+    }
+}
+
+impl Bus {
+    // Should be ticked every M cycle
+    pub fn tick_dma(&mut self) {
+        if let Some((source, delay)) = self.dma.pending {
+            if delay > 0 {
+                self.dma.pending = Some((source, delay - 1));
+            } else {
+                // A new transfer replaces the running one
+                self.dma.pending = None;
+                self.dma.source = source;
+                self.dma.index = 0;
+                self.dma.active = true;
+            }
+        }
+
+        if !self.dma.active {
+            return;
+        }
+
+        let value = self.dma_source_read(self.dma.source + self.dma.index);
+        self.ram.write(0xFE00 + self.dma.index, value);
+        self.dma.value = value;
+        self.dma.index += 1;
+        if self.dma.index == OAM_DMA_LEN {
+            self.dma.active = false;
+        }
+    }
+
+    // Value seen by the CPU when accessing addr during a transfer.
+    // None means the access is not affected by the DMA.
+    pub(super) fn dma_conflict(&self, addr: u16) -> Option<u8> {
+        if !self.dma.active {
+            return None;
+        }
+
+        match addr {
+            0xFE00..=0xFEFF => Some(0xFF),
+            _ => {
+                let bus = DmaBus::from_addr(addr)?;
+                if Some(bus) == DmaBus::from_addr(self.dma.source) {
+                    Some(self.dma.value)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // Reads the DMA source, which bypasses the PPU access restrictions
+    pub(super) fn dma_source_read(&self, addr: u16) -> u8 {
+        match addr {
+            _ if self.boot_rom_mapped(addr) => self.boot_rom[addr as usize],
+            0x0000..0x8000 | 0xA000..0xC000 => self.cartridge.read(addr),
+            0x8000..0xA000 | 0xC000..0xE000 => self.ram.read(addr),
+            _ => self.ram.read(addr - 0x2000), // 0xE000-0xFFFF mirrors WRAM
+        }
+    }
+}
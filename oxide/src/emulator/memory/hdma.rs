@@ -0,0 +1,116 @@
+use super::*;
+use crate::emulator::memory::regdefines::*;
+use crate::emulator::state::*;
+
+/*
+ * CGB VRAM DMA (0xFF51-0xFF55).
+ * HDMA1-2 hold the source (ROM, SRAM or WRAM), HDMA3-4 the destination in
+ * the selected VRAM bank, both aligned on 16 bytes. Writing HDMA5 starts a
+ * transfer of (HDMA5 & 0x7F) + 1 blocks of 16 bytes:
+ *   - Bit 7 clear: general purpose DMA, everything is copied at once
+ *   - Bit 7 set: HBlank DMA, one block is copied at the start of each HBlank
+ * The CPU is stalled while blocks are copied. Writing HDMA5 with bit 7 clear
+ * during an HBlank DMA cancels it.
+ */
+
+const BLOCK_LEN: u16 = 0x10;
+
+// CPU M-cycles per block in normal speed, doubled in double speed mode
+const BLOCK_CYCLES: u16 = 8;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct VramDma {
+    source: u16,
+    dest: u16,      // Offset in VRAM
+    blocks: u8,     // Remaining blocks
+    hblank: bool,   // Is an HBlank DMA running ?
+}
+
+impl SaveState for VramDma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.source);
+        w.u16(self.dest);
+        w.u8(self.blocks);
+        w.bool(self.hblank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.source = r.u16()?;
+        self.dest = r.u16()?;
+        self.blocks = r.u8()?;
+        self.hblank = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Bus {
+    pub(super) fn read_hdma(&self, addr: u16) -> u8 {
+        match addr {
+            // Bit 7 is cleared while an HBlank DMA is running, and set once it
+            // is cancelled. The remaining length is kept
+            HDMA5 if self.is_cgb() => match (self.hdma.hblank, self.hdma.blocks) {
+                (_, 0) => 0xFF,
+                (true, n) => n - 1,
+                (false, n) => 0x80 | (n - 1),
+            },
+            _ => 0xFF, // HDMA1-4 are write only
+        }
+    }
+
+    pub(super) fn write_hdma(&mut self, addr: u16, value: u8) {
+        if !self.is_cgb() {
+            return;
+        }
+
+        match addr {
+            HDMA1 => self.hdma.source = (self.hdma.source & 0x00FF) | (value as u16) << 8,
+            HDMA2 => self.hdma.source = (self.hdma.source & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.hdma.dest = (self.hdma.dest & 0x00FF) | ((value & 0x1F) as u16) << 8,
+            HDMA4 => self.hdma.dest = (self.hdma.dest & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5 => {
+                if self.hdma.hblank && value & 0x80 == 0 {
+                    debug!("HBlank DMA cancelled");
+                    self.hdma.hblank = false;
+                    return;
+                }
+
+                self.hdma.blocks = (value & 0x7F) + 1;
+                if value & 0x80 != 0 {
+                    self.hdma.hblank = true;
+                    // Nothing waits for an HBlank while the LCD is off
+                    if self.ioregs[0x40] & 0x80 == 0 {
+                        self.hdma_block();
+                    }
+                } else {
+                    while self.hdma.blocks > 0 {
+                        self.hdma_block();
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // Called by the PPU when entering Mode 0
+    pub fn hblank_dma(&mut self) {
+        if self.hdma.hblank {
+            self.hdma_block();
+        }
+    }
+
+    fn hdma_block(&mut self) {
+        for i in 0..BLOCK_LEN {
+            let value = self.dma_source_read(self.hdma.source.wrapping_add(i));
+            self.ram.write(0x8000 + ((self.hdma.dest + i) & 0x1FFF), value);
+        }
+        self.hdma.source = self.hdma.source.wrapping_add(BLOCK_LEN);
+        self.hdma.dest = (self.hdma.dest + BLOCK_LEN) & 0x1FFF;
+        self.hdma.blocks -= 1;
+        if self.hdma.blocks == 0 {
+            self.hdma.hblank = false;
+        }
+
+        let speed = if self.double_speed {2} else {1};
+        self.cpu_stall += BLOCK_CYCLES * speed;
+    }
+}
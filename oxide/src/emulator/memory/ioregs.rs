@@ -1,3 +1,5 @@
+use crate::emulator::apu;
+use crate::emulator::cpu::interrupt::Interrupt;
 use crate::emulator::memory::regdefines::*;
 use crate::emulator::memory::Bus;
 use crate::emulator::ppu::Mode;
@@ -9,16 +11,21 @@ impl Bus {
     pub(super) fn read_regs(&self, addr: u16) -> u8 {
         match addr {
             JOYP => self.read_joyp(),
-            LY | SC => {
-                if GLOB_SETTINGS.get().unwrap().doctor_logs {0x90} else {0xFF}
-            }, // Temporary values to run Mooneye and GB Doctor
+            LY => {
+                // Gameboy Doctor logs are generated with LY always at 0x90
+                if GLOB_SETTINGS.get().unwrap().ly_stub {0x90} else {self.ioregs[0x44]}
+            },
+            SC => self.ioregs[0x02] | 0b0111_1110,
             STAT => {
-                let mut val: u8 = self.ioregs[0x41];
+                let mut val: u8 = self.ioregs[0x41] | 0b10000000;
                 if self.ioregs[0x40] & 0b10000000 == 0 {
                     val = val & 0b11111100;
                 }
                 val
             }
+            KEY0 | KEY1 | VBK | SVBK | BCPS..=OPRI => self.read_cgb_regs(addr),
+            HDMA1..=HDMA5 => self.read_hdma(addr),
+            NR10..WAVE_RAM => self.ioregs[addr as usize - 0xFF00] | apu::read_mask(addr),
             0xFF00..0xFF80 => self.ioregs[addr as usize - 0xFF00],
             IE => self.ioregs[0x7F],
             _ => 0x00
@@ -39,7 +46,16 @@ impl Bus {
                 debug!("SC Written. Value: {value}.");
                 self.ioregs[addr as usize - 0xFF00] = value;
             }
-            STAT => self.ioregs[0x41] = (value & 0b11111100) | (self.ioregs[0x41] & 0b11), 
+            STAT => self.ioregs[0x41] = (value & 0b01111000) | (self.ioregs[0x41] & 0b111),
+            LY => debug!("Ignored write to read-only LY register. Value: {value}."),
+            DMA => {
+                self.ioregs[0x46] = value;
+                self.dma.start(value);
+            },
+            KEY0 | KEY1 | VBK | SVBK | BCPS..=OPRI => self.write_cgb_regs(addr, value),
+            HDMA1..=HDMA5 => self.write_hdma(addr, value),
+            // Applied by the APU on its next tick
+            NR10..=NR52 => self.apu_write = Some((addr, value)),
             0xFF00..0xFF80 => self.ioregs[addr as usize - 0xFF00] = value,
             IE => self.ioregs[0x7F] = value,
             _ => ()
@@ -57,20 +73,30 @@ impl Bus {
     }
     
     fn read_joyp(&self) -> u8 {
-        let joystate = self.io_manager.get_joystate();
-        let sel = self.read(JOYP) & 0x30;             // Get Register selection bits
-        let buttons = (sel & 0b0010_0000) == 0;     // Is buttons selected
-        let dpad = (sel & 0b0001_0000) == 0;        // Is DPad selected
-        let mut result: u8 = 0;
-
-        if buttons {result |=  joystate       & 0xF}
-        if dpad    {result |= (joystate >> 4) & 0xF}
+        if self.is_sgb() {
+            // With MLT_REQ, the joypad ID is read when no line is selected
+            let sel = self.ioregs[0x00] & 0x30;
+            match self.sgb.joypad_id() {
+                Some(id) if sel == 0x30 => return 0xC0 | sel | id,
+                _ if !self.sgb.first_player() => return 0xC0 | sel | 0x0F,
+                _ => (),
+            }
+        }
+        self.io_manager.get_joyp(self.ioregs[0x00])
+    }
 
-        ((!result) & 0xF) | sel | 0xC0                    // Recompute the register
+    // Should be ticked every M cycle
+    pub fn tick_joypad(&mut self) {
+        if self.io_manager.tick(self.ioregs[0x00]) {
+            self.set_interrupt(Interrupt::Joypad);
+        }
     }
     
     fn write_joyp(&mut self, value: u8) {
         let old = self.ioregs[0x00] & 0b11001111;
         self.ioregs[0x00] = old | (value & 0b00110000);
+        if self.is_sgb() {
+            self.sgb.write_joyp(value);
+        }
     }
 }
\ No newline at end of file
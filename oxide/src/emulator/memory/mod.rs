@@ -1,11 +1,18 @@
 pub mod cartridge;
 pub mod ram;
 pub mod serial;
+pub mod dma;
+mod cgb;
+mod hdma;
+mod sgb;
+mod state;
 
 pub mod regdefines;
 mod ioregs;
 
 use cartridge::*;
+use dma::*;
+use hdma::*;
 use ram::*;
 use std::fs;
 use std::hash::{DefaultHasher, Hash, Hasher};
@@ -16,18 +23,33 @@ use log::{debug, info, warn};
 use crate::emulator::internals::iomanager::IoManager;
 use crate::emulator::memory::regdefines::STAT;
 use crate::emulator::ppu;
+use crate::emulator::sgb::Sgb;
 use crate::emulator::ppu::{Frame, Mode};
+use crate::emulator::ppu::color::CgbPalettes;
+use crate::emulator::Model;
 use crate::settings::GLOB_SETTINGS;
 use std::path::Path;
 
+const DMG_BOOT_LEN: usize = 0x100;
+const CGB_BOOT_LEN: usize = 0x900;
+
 pub struct Bus {
     pub cartridge: AnyCartridge,
     pub ram: Ram,
     pub ioregs: [u8; 0x80],
-    pub boot_rom: [u8; 256],
+    pub boot_rom: Vec<u8>,  // 256 bytes on DMG, 2304 bytes on CGB
     pub boot_enabled: bool,
-    
+    pub model: Model,
+    pub double_speed: bool,
+    pub palettes: CgbPalettes,
+    pub compat_combo_frames: u8, // Remaining frames to select the DMG compatibility palettes
+    pub sgb: Sgb,
+
     pub div_written: bool,
+    pub apu_write: Option<(u16, u8)>,
+    pub dma: OamDma,
+    pub hdma: VramDma,
+    pub cpu_stall: u16,     // M-cycles during which the CPU is halted by a VRAM DMA
     pub io_manager: IoManager,
 }
 
@@ -84,51 +106,71 @@ impl<'a> Iterator for BusIter<'a> {
 }
 
 impl Bus {
-    pub fn new<P: AsRef<Path>>(rom_path: P, boot_path: P, io_manager: IoManager) -> Result<Self, String> {
-        let raw = fs::read(boot_path);
-        let boot_rom : [u8; 256];
-        let boot_enabled : bool;
-        
-        if let Ok(res) = raw {
-            if res.len() != 256 {
-                warn!("Invalid boot ROM length. Defaulting to no boot ROM mode.");
-                boot_rom = [0; 256];
-                boot_enabled = false;
-            } else {
-                boot_rom = <Vec<u8> as TryInto<[u8; 256]>>::try_into(res).unwrap();
-                boot_enabled = true;
+    // The model is detected from the cartridge header if set to Auto
+    pub fn new<P: AsRef<Path>>(rom_path: P, boot_path: P, io_manager: IoManager, model: Model) -> Result<Self, String> {
+        let cartridge = AnyCartridge::load_from_file(rom_path)?;
+        let model = model.resolve(cartridge.header());
+        let boot_len = if model == Model::Cgb {CGB_BOOT_LEN} else {DMG_BOOT_LEN};
+
+        let (boot_rom, boot_enabled) = match fs::read(boot_path) {
+            Ok(res) if res.len() == boot_len => (res, true),
+            Ok(_) => {
+                warn!("Invalid boot ROM length for {model:?}. Defaulting to no boot ROM mode.");
+                (Vec::new(), false)
+            },
+            Err(_) => {
+                info!("Invalid boot ROM path. Defaulting to no boot ROM mode.");
+                (Vec::new(), false)
             }
-        } else {
-            info!("Invalid boot ROM path. Defaulting to no boot ROM mode.");
-            boot_rom = [0; 256];
-            boot_enabled = false;
-        }
-        
-        Ok(Bus {
-            cartridge: AnyCartridge::load_from_file(rom_path)?,
-            ram: Ram::new(),
+        };
+
+        let mut bus = Bus {
+            cartridge,
+            ram: Ram::new(model == Model::Cgb),
             ioregs: [0; 0x80],
             boot_rom,
             boot_enabled,
-            
+            model,
+            double_speed: false,
+            palettes: CgbPalettes::new(GLOB_SETTINGS.get().unwrap().color_correction),
+            compat_combo_frames: 0,
+            sgb: Sgb::default(),
+
             div_written: false,
+            apu_write: None,
+            dma: OamDma::default(),
+            hdma: VramDma::default(),
+            cpu_stall: 0,
             io_manager,
-        })
+        };
+
+        // The CGB boot ROM sets up the compatibility mode by itself
+        if model == Model::Cgb && !bus.cartridge.header().is_cgb() && !bus.boot_enabled {
+            bus.init_dmg_compat();
+        }
+        Ok(bus)
+    }
+
+    // The CGB boot ROM leaves the cartridge header visible at 0x0100-0x01FF
+    pub fn boot_rom_mapped(&self, addr: u16) -> bool {
+        self.boot_enabled && match addr {
+            0x0000..0x0100 => true,
+            0x0200..0x0900 => self.boot_rom.len() == CGB_BOOT_LEN,
+            _ => false,
+        }
     }
 
     pub fn read(&self, addr: u16) -> u8 {
         #[cfg(feature = "log_mem_access")]
         debug!("Memory read: 0x{:#06X}", addr);
 
+        if let Some(value) = self.dma_conflict(addr) {
+            return value;
+        }
+
         match addr {
-            0x0000..0x100 => {
-                if !self.boot_enabled {
-                    self.cartridge.read(addr)
-                } else {
-                    self.boot_rom[addr as usize]
-                }
-            }
-            0x0100..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.read(addr),
+            _ if self.boot_rom_mapped(addr) => self.boot_rom[addr as usize],
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.read(addr),
 
             // VRAM
             0x8000..=0x9FFF => {
@@ -161,8 +203,15 @@ impl Bus {
         #[cfg(feature = "log_mem_access")]
         debug!("Memory write: 0x{:#04X} => 0x{:#06X}", value, addr);
 
+        if self.dma_conflict(addr).is_some() {
+            return;
+        }
+
         match addr {
-            0x0000..=0x7FFF => self.cartridge.write(addr, value),
+            0x0000..=0x7FFF => {
+                self.cartridge.write(addr, value);
+                self.io_manager.set_rumble(self.cartridge.rumble());
+            },
             0xA000..=0xBFFF => {
                 if self.cartridge.is_writeable(addr) {
                     self.cartridge.write(addr, value)
@@ -250,6 +299,8 @@ impl Bus {
     }
     
     pub fn send_frame(&mut self, frame: Frame) {
+        // The PPU draws shades, which are colorized with the border
+        let frame = if self.is_sgb() {self.sgb.compose(&frame)} else {frame};
         self.io_manager.send_frame(frame);
     }
 }
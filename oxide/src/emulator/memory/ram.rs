@@ -1,5 +1,6 @@
 #[allow(unused_imports)]
 use log::{debug, info, warn};
+use crate::emulator::state::*;
 
 pub struct Ram {
     vram: Vec<[u8; 0x2000]>,
@@ -12,11 +13,13 @@ pub struct Ram {
 }
 
 impl Ram {
-    pub fn new() -> Ram {
+    // The CGB has 2 VRAM banks and 7 switchable WRAM banks
+    pub fn new(cgb: bool) -> Ram {
+        let (vram_count, wram_count) = if cgb {(2, 7)} else {(1, 1)};
         Ram {
-            vram: vec![[0; 0x2000]; 1],
+            vram: vec![[0; 0x2000]; vram_count],
             wram: [0; 0x1000],
-            wram_banks: vec![[0; 0x1000]; 1],
+            wram_banks: vec![[0; 0x1000]; wram_count],
             hram: [0; 0x80],
             oam: [0; 0xA0],
             cur_wram: 0,
@@ -24,6 +27,29 @@ impl Ram {
         }
     }
 
+    pub fn vram_bank(&self) -> usize {
+        self.cur_vram
+    }
+
+    pub fn set_vram_bank(&mut self, bank: usize) {
+        self.cur_vram = bank % self.vram.len();
+    }
+
+    // WRAM bank mapped at 0xD000-0xDFFF, from 1 to 7
+    pub fn wram_bank(&self) -> usize {
+        self.cur_wram + 1
+    }
+
+    // Bank 0 selects bank 1
+    pub fn set_wram_bank(&mut self, bank: usize) {
+        self.cur_wram = (bank.max(1) - 1) % self.wram_banks.len();
+    }
+
+    // VRAM access used by the PPU, regardless of the selected bank
+    pub fn read_vram(&self, bank: usize, addr: u16) -> u8 {
+        self.vram[bank][(addr - 0x8000) as usize]
+    }
+
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
             0x8000..=0x9FFF => self.vram[self.cur_vram][(addr - 0x8000) as usize],
@@ -62,3 +88,38 @@ impl Ram {
         }
     }
 }
+
+impl SaveState for Ram {
+    fn save_state(&self, w: &mut StateWriter) {
+        for bank in &self.vram {
+            w.bytes(bank);
+        }
+        w.bytes(&self.wram);
+        for bank in &self.wram_banks {
+            w.bytes(bank);
+        }
+        w.bytes(&self.hram);
+        w.bytes(&self.oam);
+        w.u8(self.cur_wram as u8);
+        w.u8(self.cur_vram as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for bank in &mut self.vram {
+            r.bytes_into(bank)?;
+        }
+        r.bytes_into(&mut self.wram)?;
+        for bank in &mut self.wram_banks {
+            r.bytes_into(bank)?;
+        }
+        r.bytes_into(&mut self.hram)?;
+        r.bytes_into(&mut self.oam)?;
+        self.set_wram_bank(r.u8()? as usize + 1);
+        self.set_vram_bank(r.u8()? as usize);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/ram.rs"]
+mod ram_tests;
@@ -16,10 +16,59 @@ pub const TAC: u16  = 0xFF07;
 pub const IF: u16   = 0xFF0F;
 pub const IE: u16   = 0xFFFF;
 
+/* Sound */
+pub const NR10: u16 = 0xFF10;
+pub const NR11: u16 = 0xFF11;
+pub const NR12: u16 = 0xFF12;
+pub const NR13: u16 = 0xFF13;
+pub const NR14: u16 = 0xFF14;
+pub const NR21: u16 = 0xFF16;
+pub const NR22: u16 = 0xFF17;
+pub const NR23: u16 = 0xFF18;
+pub const NR24: u16 = 0xFF19;
+pub const NR30: u16 = 0xFF1A;
+pub const NR31: u16 = 0xFF1B;
+pub const NR32: u16 = 0xFF1C;
+pub const NR33: u16 = 0xFF1D;
+pub const NR34: u16 = 0xFF1E;
+pub const NR41: u16 = 0xFF20;
+pub const NR42: u16 = 0xFF21;
+pub const NR43: u16 = 0xFF22;
+pub const NR44: u16 = 0xFF23;
+pub const NR50: u16 = 0xFF24;
+pub const NR51: u16 = 0xFF25;
+pub const NR52: u16 = 0xFF26;
+pub const WAVE_RAM: u16 = 0xFF30;
+
 /* LCD */
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16  = 0xFF42;
+pub const SCX: u16  = 0xFF43;
 pub const LY: u16   = 0xFF44;
 pub const LYC: u16  = 0xFF45;
-pub const STAT: u16 = 0xFF41;
+pub const DMA: u16  = 0xFF46;
+pub const BGP: u16  = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16   = 0xFF4A;
+pub const WX: u16   = 0xFF4B;
 
 /* Misc */
-pub const BANK: u16 = 0xFF50;
\ No newline at end of file
+pub const BANK: u16 = 0xFF50;
+
+/* CGB */
+pub const KEY0: u16 = 0xFF4C;
+pub const KEY1: u16 = 0xFF4D;
+pub const VBK: u16  = 0xFF4F;
+pub const HDMA1: u16 = 0xFF51;
+pub const HDMA2: u16 = 0xFF52;
+pub const HDMA3: u16 = 0xFF53;
+pub const HDMA4: u16 = 0xFF54;
+pub const HDMA5: u16 = 0xFF55;
+pub const BCPS: u16 = 0xFF68;
+pub const BCPD: u16 = 0xFF69;
+pub const OCPS: u16 = 0xFF6A;
+pub const OCPD: u16 = 0xFF6B;
+pub const OPRI: u16 = 0xFF6C;
+pub const SVBK: u16 = 0xFF70;
\ No newline at end of file
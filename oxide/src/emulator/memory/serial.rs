@@ -8,9 +8,13 @@ impl Bus {
                 if GLOB_SETTINGS.get().unwrap().print_serial {
                     let c = self.ioregs[0x01] as char;
                     emu_print!("{c}");
-                    self.ioregs[0x01] = 0xFF;
-                    self.ioregs[0x02] &= 0x7F;
                 }
+                if let Some(log) = &mut self.io_manager.serial_log {
+                    log.push(self.ioregs[0x01]);
+                }
+                // No link cable: the transfer completes and receives 0xFF
+                self.ioregs[0x01] = 0xFF;
+                self.ioregs[0x02] &= 0x7F;
             },
             _ => ()
         }
@@ -0,0 +1,40 @@
+use super::*;
+use crate::emulator::sgb::TRANSFER_LEN;
+
+/*
+ * SGB glue.
+ * Packets are decoded from the JOYP writes (see ioregs.rs). VRAM transfers
+ * read the 256 tiles displayed on the first 13 rows of the screen, in BG map
+ * order, as the SGB captures them from the LCD output.
+ */
+
+impl Bus {
+    pub fn is_sgb(&self) -> bool {
+        self.model == Model::Sgb
+    }
+
+    // Called on each VBlank
+    pub fn sgb_vblank(&mut self) {
+        if self.is_sgb() && self.sgb.transfer_pending() {
+            let data = self.sgb_transfer_data();
+            self.sgb.finish_transfer(&data);
+        }
+    }
+
+    fn sgb_transfer_data(&self) -> Vec<u8> {
+        let lcdc = self.ioregs[0x40];
+        let map = if lcdc & ppu::LCDC_BG_MAP != 0 {0x9C00} else {0x9800};
+        let mut data = Vec::with_capacity(TRANSFER_LEN);
+
+        for i in 0..(TRANSFER_LEN / 16) as u16 {
+            let tile = self.ram.read_vram(0, map + (i / 20) * 32 + i % 20);
+            let addr = if lcdc & ppu::LCDC_TILE_DATA != 0 {
+                0x8000 + tile as u16 * 16
+            } else {
+                (0x9000i32 + (tile as i8) as i32 * 16) as u16
+            };
+            data.extend((addr..addr + 16).map(|a| self.ram.read_vram(0, a)));
+        }
+        data
+    }
+}
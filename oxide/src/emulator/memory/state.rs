@@ -0,0 +1,46 @@
+use super::*;
+use crate::emulator::state::*;
+
+impl SaveState for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ioregs);
+        w.bool(self.boot_enabled);
+        w.bool(self.double_speed);
+        w.u8(self.compat_combo_frames);
+        w.bool(self.div_written);
+        w.bool(self.apu_write.is_some());
+        let (addr, value) = self.apu_write.unwrap_or_default();
+        w.u16(addr);
+        w.u8(value);
+        w.u16(self.cpu_stall);
+
+        self.ram.save_state(w);
+        self.cartridge.save_state(w);
+        self.dma.save_state(w);
+        self.hdma.save_state(w);
+        self.palettes.save_state(w);
+        self.sgb.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.ioregs)?;
+        self.boot_enabled = r.bool()?;
+        if self.boot_enabled && self.boot_rom.is_empty() {
+            return Err("The save state was made while running the boot ROM, which is not loaded".to_string());
+        }
+        self.double_speed = r.bool()?;
+        self.compat_combo_frames = r.u8()?;
+        self.div_written = r.bool()?;
+        let pending = r.bool()?;
+        let write = (r.u16()?, r.u8()?);
+        self.apu_write = pending.then_some(write);
+        self.cpu_stall = r.u16()?;
+
+        self.ram.load_state(r)?;
+        self.cartridge.load_state(r)?;
+        self.dma.load_state(r)?;
+        self.hdma.load_state(r)?;
+        self.palettes.load_state(r)?;
+        self.sgb.load_state(r)
+    }
+}
@@ -0,0 +1,41 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::ram::*;
+
+    #[test]
+    fn test_vram_banks() {
+        let mut ram = Ram::new(true);
+        ram.write(0x8000, 0x11);
+        ram.set_vram_bank(1);
+        assert_eq!(ram.read(0x8000), 0x00, "Bank 1 is separate");
+        ram.write(0x9FFF, 0x22);
+
+        assert_eq!(ram.read_vram(0, 0x8000), 0x11, "PPU access to bank 0");
+        assert_eq!(ram.read_vram(1, 0x9FFF), 0x22, "PPU access to bank 1");
+        assert_eq!(ram.vram_bank(), 1);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mut ram = Ram::new(true);
+        for bank in 1..8 {
+            ram.set_wram_bank(bank);
+            ram.write(0xD000, bank as u8);
+        }
+        ram.set_wram_bank(0);
+        assert_eq!(ram.wram_bank(), 1, "Bank 0 selects bank 1");
+        assert_eq!(ram.read(0xD000), 1);
+        ram.set_wram_bank(5);
+        assert_eq!(ram.read(0xD000), 5, "Bank 5");
+        assert_eq!(ram.read(0xF000), 5, "Echo RAM follows the bank");
+    }
+
+    #[test]
+    fn test_dmg_single_banks() {
+        let mut ram = Ram::new(false);
+        ram.write(0x8000, 0x33);
+        ram.set_vram_bank(1);
+        assert_eq!(ram.read(0x8000), 0x33, "No VRAM bank 1 on DMG");
+    }
+}
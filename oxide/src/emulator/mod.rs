@@ -1,5 +1,8 @@
 pub mod memory;
 pub mod ppu;
+pub mod apu;
+pub mod sgb;
+pub mod state;
 
 pub mod cpu;
 pub mod internals;
@@ -7,6 +10,7 @@ pub mod internals;
 use cpu::*;
 use memory::*;
 use ppu::*;
+use apu::Apu;
 
 use crate::debugger::*;
 
@@ -14,37 +18,85 @@ use crate::debugger::*;
 use crate::emu_print;
 use crate::emulator::internals::iomanager::IoManager;
 use crate::emulator::internals::timer::Timer;
+use crate::emulator::memory::cartridge::header::CartridgeHeader;
 use crate::settings::GLOB_SETTINGS;
+use clap::ValueEnum;
+use log::{error, info};
 use std::path::Path;
 
+// Save RAM is written to disk every 5 seconds of emulated time if modified
+const SAVE_FLUSH_TICKS: usize = 5 * 4_194_304;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum Model {
+    /// Chosen from the cartridge header
+    #[default]
+    Auto,
+
+    /// Original Game Boy
+    Dmg,
+
+    /// Game Boy Color
+    Cgb,
+
+    /// Super Game Boy
+    Sgb,
+}
+
+impl Model {
+    // CGB enhanced and CGB only cartridges run on a CGB, SGB enhanced ones
+    // on a SGB
+    pub fn resolve(self, header: &CartridgeHeader) -> Model {
+        match self {
+            Model::Auto if header.is_cgb() => Model::Cgb,
+            Model::Auto if header.is_sgb() => Model::Sgb,
+            Model::Auto => Model::Dmg,
+            model => model,
+        }
+    }
+}
+
 pub struct Emulator {
     pub cpu: Cpu,
     pub bus: Bus,
     pub ppu: Ppu,
     
     pub timer: Timer,
+    pub apu: Apu,
     
     pub ticks: usize
 }
 
 impl Emulator {
-    pub fn new<P: AsRef<Path>>(rom_path: P, boot_path: P, io_manager: IoManager) -> Result<Self, String> {
-        let bus = Bus::new(rom_path, boot_path, io_manager)?;
-        let cpu = if bus.boot_enabled {
-            Cpu::new_boot()
-        } else {
-            Cpu::new_noboot()
+    pub fn new<P: AsRef<Path>>(rom_path: P, boot_path: P, io_manager: IoManager, model: Model) -> Result<Self, String> {
+        let mut bus = Bus::new(rom_path, boot_path, io_manager, model)?;
+        let cpu = match (bus.boot_enabled, bus.model) {
+            (true, _) => Cpu::new_boot(),
+            (false, Model::Cgb) => Cpu::new_cgb(),
+            (false, Model::Sgb) => Cpu::new_sgb(),
+            (false, _) => Cpu::new_noboot(),
         };
+        info!("Running in {:?} mode", bus.model);
 
-        if GLOB_SETTINGS.get().unwrap().doctor_logs {
+        let settings = GLOB_SETTINGS.get().unwrap();
+        if settings.doctor_logs {
             emu_print!("{}", cpu.get_doctor_log(&bus))
         }
 
+        let mut apu = Apu::new(settings.sample_rate);
+        if !bus.boot_enabled {
+            apu.init_noboot(&mut bus);
+        }
+        if let Some(path) = &settings.record_audio {
+            apu.start_recording(path, settings.record_channels)?;
+        }
+
         Ok(Emulator{
             cpu,
             bus,
-            ppu: Default::default(),
+            ppu: Ppu::new(settings.ppu_backend),
             timer: Timer::default(),
+            apu,
             
             ticks: 0
         })
@@ -58,17 +110,47 @@ impl Emulator {
         self.ticks / 4
     }
 
+    // Writes the battery backed RAM to its save file
+    pub fn save(&mut self) {
+        if let Err(e) = self.bus.cartridge.save() {
+            error!("{e}");
+        }
+    }
+
     pub fn tick<T>(&mut self, dbg: &mut T)
     where T: Debugger {
         self.ticks = self.ticks.wrapping_add(1);
         
-        if self.ticks & 0b11 == 0 { // M-Cycle
-            self.cpu.tick(&mut self.bus, dbg);
+        // The RTC crystal does not follow the CPU speed
+        if self.ticks & 0b11 == 0 {
+            self.bus.cartridge.tick();
         }
-        
+
+        // M-Cycle, twice as often in double speed mode
+        let m_cycle_mask = if self.bus.double_speed {0b1} else {0b11};
+        if self.ticks & m_cycle_mask == 0 {
+            self.bus.tick_dma();
+            self.bus.tick_joypad();
+            // The CPU is halted while a VRAM DMA copies blocks
+            if self.bus.cpu_stall > 0 {
+                self.bus.cpu_stall -= 1;
+            } else {
+                self.cpu.tick(&mut self.bus, dbg);
+            }
+        }
+
         // T-Cycle
         self.bus.tick_serial();
         self.ppu.tick(&mut self.bus, dbg);
         self.timer.tick(&mut self.bus);
+        if self.bus.double_speed {
+            self.timer.tick(&mut self.bus);
+        }
+        self.apu.tick(&mut self.bus);
+
+        if self.ticks.is_multiple_of(SAVE_FLUSH_TICKS)
+            && let Err(e) = self.bus.cartridge.flush_save() {
+            error!("{e}");
+        }
     }
 }
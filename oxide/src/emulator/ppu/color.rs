@@ -0,0 +1,122 @@
+/*
+ * CGB palettes.
+ * BG and OBJ palette RAM hold 8 palettes of 4 RGB555 colors each (64 bytes),
+ * accessed through an index register (BCPS/OCPS) with optional
+ * auto-increment and a data register (BCPD/OCPD).
+ * Colors can be corrected to mimic the washed out CGB LCD instead of
+ * displaying the raw values at full saturation.
+ */
+
+use crate::emulator::state::*;
+
+const AUTO_INCREMENT: u8 = 0b1000_0000;
+
+#[derive(Debug, Copy, Clone)]
+pub struct PaletteRam {
+    data: [u8; 64],
+    spec: u8, // BCPS/OCPS: index and auto-increment bit
+}
+
+impl Default for PaletteRam {
+    fn default() -> Self {
+        // The CGB boot ROM initializes the BG palettes to white
+        PaletteRam { data: [0xFF; 64], spec: 0 }
+    }
+}
+
+impl PaletteRam {
+    pub fn read_spec(&self) -> u8 {
+        self.spec | 0x40
+    }
+
+    pub fn write_spec(&mut self, value: u8) {
+        self.spec = value & 0xBF;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data[(self.spec & 0x3F) as usize]
+    }
+
+    // The index is incremented even if the write is blocked during Mode 3
+    pub fn write_data(&mut self, value: u8, blocked: bool) {
+        if !blocked {
+            self.data[(self.spec & 0x3F) as usize] = value;
+        }
+        if self.spec & AUTO_INCREMENT != 0 {
+            self.spec = AUTO_INCREMENT | ((self.spec + 1) & 0x3F);
+        }
+    }
+
+    // RGB555 value of a color of a palette
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let i = (palette as usize & 7) * 8 + color as usize * 2;
+        u16::from_le_bytes([self.data[i], self.data[i + 1]])
+    }
+
+    pub fn set_color(&mut self, palette: u8, color: u8, value: u16) {
+        let i = (palette as usize & 7) * 8 + color as usize * 2;
+        self.data[i..i + 2].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CgbPalettes {
+    pub bg: PaletteRam,
+    pub obj: PaletteRam,
+    pub color_correction: bool,
+}
+
+impl CgbPalettes {
+    pub fn new(color_correction: bool) -> Self {
+        CgbPalettes { color_correction, ..Default::default() }
+    }
+
+    pub fn bg_rgba(&self, palette: u8, color: u8) -> u32 {
+        rgb555_to_rgba(self.bg.color(palette, color), self.color_correction)
+    }
+
+    pub fn obj_rgba(&self, palette: u8, color: u8) -> u32 {
+        rgb555_to_rgba(self.obj.color(palette, color), self.color_correction)
+    }
+}
+
+// Color correction is a setting of the emulator, not part of the state
+impl SaveState for CgbPalettes {
+    fn save_state(&self, w: &mut StateWriter) {
+        for ram in [&self.bg, &self.obj] {
+            w.bytes(&ram.data);
+            w.u8(ram.spec);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for ram in [&mut self.bg, &mut self.obj] {
+            r.bytes_into(&mut ram.data)?;
+            ram.spec = r.u8()?;
+        }
+        Ok(())
+    }
+}
+
+// Converts a RGB555 color to the frame format (0xAARRGGBB)
+pub fn rgb555_to_rgba(color: u16, correction: bool) -> u32 {
+    let r = (color & 0x1F) as u32;
+    let g = ((color >> 5) & 0x1F) as u32;
+    let b = ((color >> 10) & 0x1F) as u32;
+
+    let (r, g, b) = if correction {
+        // Channels bleed into each other and the brightest values are compressed
+        (
+            (r * 26 + g * 4 + b * 2).min(960) >> 2,
+            (g * 24 + b * 8).min(960) >> 2,
+            (r * 6 + g * 4 + b * 22).min(960) >> 2,
+        )
+    } else {
+        ((r << 3) | (r >> 2), (g << 3) | (g >> 2), (b << 3) | (b >> 2))
+    };
+    0xFF00_0000 | r << 16 | g << 8 | b
+}
+
+#[cfg(test)]
+#[path = "tests/color.rs"]
+mod color_tests;
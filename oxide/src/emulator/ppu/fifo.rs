@@ -0,0 +1,326 @@
+use super::*;
+use super::objects::*;
+use crate::emulator::memory::regdefines::*;
+use crate::emulator::state::*;
+
+use std::collections::VecDeque;
+
+/*
+ * Pixel FIFO renderer.
+ * Models the background fetcher and the BG/OBJ pixel FIFOs dot by dot.
+ * Registers are sampled when the fetcher or the shifter uses them, so
+ * mid-scanline writes take effect at the right pixel and Mode 3 length
+ * depends on SCX, the window and the fetched objects.
+ */
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FetchStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct ObjPixel {
+    color: u8,
+    flags: u8,
+    index: u8, // OAM index, for CGB priority
+}
+
+#[derive(Debug)]
+pub(super) struct PixelFifo {
+    bg: VecDeque<(u8, u8)>, // Color index and CGB attributes
+    obj: VecDeque<ObjPixel>,
+
+    step: FetchStep,
+    step_dots: u8,   // Dots spent in the current fetcher step
+    fetch_x: u8,     // Tile column of the fetcher
+    tile: u8,
+    attrs: u8,       // CGB attributes of the tile
+    row: u8,         // Tile row being fetched
+    lo: u8,
+    hi: u8,
+    first_fetch: bool, // The first fetch of a line is thrown away
+
+    lx: u8,          // Next screen column to output
+    discard: u8,     // Pixels to drop for SCX fine scroll
+    window: bool,    // Is the fetcher drawing the window ?
+
+    next_obj: usize,         // Next object of the line to fetch
+    obj_dots: Option<u8>,    // Remaining dots of the current object fetch
+}
+
+impl Default for PixelFifo {
+    fn default() -> Self {
+        PixelFifo {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+
+            step: FetchStep::Tile,
+            step_dots: 0,
+            fetch_x: 0,
+            tile: 0,
+            attrs: 0,
+            row: 0,
+            lo: 0,
+            hi: 0,
+            first_fetch: true,
+
+            lx: 0,
+            discard: 0,
+            window: false,
+
+            next_obj: 0,
+            obj_dots: None,
+        }
+    }
+}
+
+impl PixelFifo {
+    fn restart_fetcher(&mut self) {
+        self.step = FetchStep::Tile;
+        self.step_dots = 0;
+    }
+
+    // VRAM bank of the tile being fetched
+    fn bank(&self) -> usize {
+        if self.attrs & BG_ATTR_BANK != 0 {1} else {0}
+    }
+}
+
+const STEPS: [FetchStep; 4] = [FetchStep::Tile, FetchStep::DataLow, FetchStep::DataHigh, FetchStep::Push];
+
+impl SaveState for PixelFifo {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.bg.len() as u8);
+        for &(color, attrs) in &self.bg {
+            w.u8(color);
+            w.u8(attrs);
+        }
+        w.u8(self.obj.len() as u8);
+        for pixel in &self.obj {
+            w.u8(pixel.color);
+            w.u8(pixel.flags);
+            w.u8(pixel.index);
+        }
+
+        w.u8(self.step as u8);
+        for value in [self.step_dots, self.fetch_x, self.tile, self.attrs, self.row, self.lo, self.hi] {
+            w.u8(value);
+        }
+        w.bool(self.first_fetch);
+
+        w.u8(self.lx);
+        w.u8(self.discard);
+        w.bool(self.window);
+
+        w.u8(self.next_obj as u8);
+        w.bool(self.obj_dots.is_some());
+        w.u8(self.obj_dots.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let len = r.u8()?;
+        self.bg = (0..len).map(|_| Ok((r.u8()?, r.u8()?))).collect::<Result<_, String>>()?;
+        let len = r.u8()?;
+        self.obj = (0..len).map(|_| Ok(ObjPixel { color: r.u8()?, flags: r.u8()?, index: r.u8()? }))
+            .collect::<Result<_, String>>()?;
+
+        let step = r.u8()?;
+        self.step = *STEPS.get(step as usize).ok_or_else(|| invalid_tag("fetcher step", step))?;
+        for value in [&mut self.step_dots, &mut self.fetch_x, &mut self.tile, &mut self.attrs,
+                      &mut self.row, &mut self.lo, &mut self.hi] {
+            *value = r.u8()?;
+        }
+        self.first_fetch = r.bool()?;
+
+        self.lx = r.u8()?;
+        self.discard = r.u8()?;
+        self.window = r.bool()?;
+
+        self.next_obj = r.u8()? as usize;
+        let fetching = r.bool()?;
+        let dots = r.u8()?;
+        self.obj_dots = fetching.then_some(dots);
+        Ok(())
+    }
+}
+
+impl Ppu {
+    // Called when entering Mode 3
+    pub(super) fn fifo_start_line(&mut self, bus: &Bus) {
+        self.fifo = PixelFifo {
+            discard: bus.read(SCX) % 8,
+            ..Default::default()
+        };
+    }
+
+    // Called when leaving Mode 3
+    pub(super) fn fifo_end_line(&mut self) {
+        if self.fifo.window {
+            self.win_line += 1;
+        }
+    }
+
+    // Runs the FIFO for one dot. Returns true when the line is complete.
+    pub(super) fn fifo_dot(&mut self, bus: &Bus) -> bool {
+        let lcdc = bus.read(LCDC);
+
+        self.check_window_start(bus, lcdc);
+
+        if self.fifo.obj_dots.is_none() && lcdc & LCDC_OBJ_ENABLE != 0 {
+            self.check_object_start();
+        }
+
+        if let Some(dots) = self.fifo.obj_dots {
+            // The BG fetch in progress has to complete before the object is fetched
+            if self.fifo.bg.is_empty() {
+                self.fetcher_dot(bus, lcdc);
+            } else if dots > 1 {
+                self.fifo.obj_dots = Some(dots - 1);
+            } else {
+                self.fetch_object(bus);
+            }
+            return false;
+        }
+
+        self.fetcher_dot(bus, lcdc);
+        self.shift_pixel(bus, lcdc);
+        self.fifo.lx as usize == GB_W
+    }
+
+    fn check_window_start(&mut self, bus: &Bus, lcdc: u8) {
+        let fifo = &mut self.fifo;
+        if fifo.window || lcdc & LCDC_WIN_ENABLE == 0 || !self.wy_triggered {
+            return;
+        }
+
+        let wx = bus.read(WX);
+        if wx <= 166 && fifo.lx as u16 + 7 >= wx as u16 {
+            fifo.window = true;
+            fifo.bg.clear();
+            fifo.fetch_x = 0;
+            fifo.restart_fetcher();
+        }
+    }
+
+    fn check_object_start(&mut self) {
+        while let Some(obj) = self.line_objects.get(self.fifo.next_obj) {
+            if obj.x == 0 || obj.x >= 168 {
+                // Never visible
+                self.fifo.next_obj += 1;
+            } else if obj.x as u16 <= self.fifo.lx as u16 + 8 {
+                self.fifo.obj_dots = Some(6);
+                return;
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn fetch_object(&mut self, bus: &Bus) {
+        let obj = self.line_objects[self.fifo.next_obj];
+        let (lo, hi) = Self::object_row(bus, &obj, self.ly, self.obj_height);
+        let oam_priority = bus.oam_priority();
+        let fifo = &mut self.fifo;
+
+        for i in 0..8u8 {
+            let col = obj.x as i16 - 8 + i as i16;
+            if col < fifo.lx as i16 {
+                continue;
+            }
+
+            let px = if obj.flags & OBJ_X_FLIP != 0 {7 - i} else {i};
+            let pixel = ObjPixel { color: Self::tile_pixel(lo, hi, px), flags: obj.flags, index: obj.index };
+            let slot = (col - fifo.lx as i16) as usize;
+            if slot >= fifo.obj.len() {
+                fifo.obj.push_back(pixel);
+            } else if fifo.obj[slot].color == 0
+                || (oam_priority && pixel.color != 0 && pixel.index < fifo.obj[slot].index) {
+                // Objects already in the FIFO have a smaller X and keep priority,
+                // unless the CGB priority by OAM index is used
+                fifo.obj[slot] = pixel;
+            }
+        }
+
+        fifo.next_obj += 1;
+        fifo.obj_dots = None;
+    }
+
+    fn fetcher_dot(&mut self, bus: &Bus, lcdc: u8) {
+        let fifo = &mut self.fifo;
+
+        if fifo.step != FetchStep::Push {
+            fifo.step_dots += 1;
+            if fifo.step_dots < 2 {
+                return;
+            }
+            fifo.step_dots = 0;
+        }
+
+        match fifo.step {
+            FetchStep::Tile => {
+                let (map, x, y) = if fifo.window {
+                    let map = if lcdc & LCDC_WIN_MAP != 0 {0x9C00} else {0x9800};
+                    (map, fifo.fetch_x, self.win_line)
+                } else {
+                    let map = if lcdc & LCDC_BG_MAP != 0 {0x9C00} else {0x9800};
+                    let x = (bus.read(SCX) / 8).wrapping_add(fifo.fetch_x) & 31;
+                    (map, x, self.ly.wrapping_add(bus.read(SCY)))
+                };
+                let map_addr = map + (y as u16 / 8) * 32 + x as u16;
+                fifo.tile = bus.ram.read_vram(0, map_addr);
+                fifo.attrs = Self::read_bg_attrs(bus, map_addr);
+                fifo.row = if fifo.attrs & BG_ATTR_Y_FLIP != 0 {7 - y % 8} else {y % 8};
+                fifo.step = FetchStep::DataLow;
+            },
+            FetchStep::DataLow => {
+                let addr = Self::bg_tile_addr(lcdc, fifo.tile) + fifo.row as u16 * 2;
+                fifo.lo = bus.ram.read_vram(fifo.bank(), addr);
+                fifo.step = FetchStep::DataHigh;
+            },
+            FetchStep::DataHigh => {
+                let addr = Self::bg_tile_addr(lcdc, fifo.tile) + fifo.row as u16 * 2;
+                fifo.hi = bus.ram.read_vram(fifo.bank(), addr + 1);
+                fifo.step = FetchStep::Push;
+            },
+            FetchStep::Push => {
+                if fifo.first_fetch {
+                    fifo.first_fetch = false;
+                    fifo.restart_fetcher();
+                } else if fifo.bg.is_empty() {
+                    for x in 0..8 {
+                        let px = if fifo.attrs & BG_ATTR_X_FLIP != 0 {7 - x} else {x};
+                        fifo.bg.push_back((Self::tile_pixel(fifo.lo, fifo.hi, px), fifo.attrs));
+                    }
+                    fifo.fetch_x = fifo.fetch_x.wrapping_add(1);
+                    fifo.restart_fetcher();
+                }
+            },
+        }
+    }
+
+    fn shift_pixel(&mut self, bus: &Bus, lcdc: u8) {
+        let Some((mut bg, attrs)) = self.fifo.bg.pop_front() else {
+            return;
+        };
+
+        if self.fifo.discard > 0 && !self.fifo.window {
+            self.fifo.discard -= 1;
+            return;
+        }
+
+        // On CGB, LCDC bit 0 only affects priority
+        if lcdc & LCDC_BG_ENABLE == 0 && !bus.is_cgb() {
+            bg = 0;
+        }
+        let obj = self.fifo.obj.pop_front()
+            .filter(|p| p.color != 0 && lcdc & LCDC_OBJ_ENABLE != 0)
+            .map(|p| (p.color, p.flags));
+
+        let x = self.fifo.lx as usize;
+        self.frame[self.ly as usize * GB_W + x] = Self::mix_pixel(bus, bg, attrs, obj);
+        self.fifo.lx += 1;
+    }
+}
@@ -1,14 +1,90 @@
+pub mod objects;
+pub mod color;
+pub mod compat;
+mod scanline;
+mod fifo;
+mod state;
+
 use super::memory::*;
+use crate::emulator::memory::regdefines::*;
+use fifo::PixelFifo;
+use objects::*;
 
-use crate::debugger::Debugger;
+use crate::debugger::{DebugEvent, Debugger};
+use crate::emulator::cpu::interrupt::Interrupt;
+use clap::ValueEnum;
+use crate::emulator::sgb::{SGB_FB_LEN, SGB_H, SGB_W};
 
 pub const GB_W: usize = 160;
 pub const GB_H: usize = 144;
 pub const FB_LEN: usize = GB_W * GB_H;
 pub type Frame = Box<[u32]>; // RGBA8888
-#[derive(Debug, Default)]
+
+pub const DOTS_PER_LINE: u16 = 456;
+pub const LINES_PER_FRAME: u8 = 154;
+const OAM_SCAN_DOTS: u16 = 80;
+const DRAW_DOTS: u16 = 172;
+
+/* LCDC bits */
+pub const LCDC_BG_ENABLE: u8  = 0b0000_0001;
+pub const LCDC_OBJ_ENABLE: u8 = 0b0000_0010;
+pub const LCDC_OBJ_SIZE: u8   = 0b0000_0100;
+pub const LCDC_BG_MAP: u8     = 0b0000_1000;
+pub const LCDC_TILE_DATA: u8  = 0b0001_0000;
+pub const LCDC_WIN_ENABLE: u8 = 0b0010_0000;
+pub const LCDC_WIN_MAP: u8    = 0b0100_0000;
+pub const LCDC_LCD_ENABLE: u8 = 0b1000_0000;
+
+/* CGB BG map attributes bits, in VRAM bank 1 */
+pub const BG_ATTR_PALETTE: u8  = 0b0000_0111;
+pub const BG_ATTR_BANK: u8     = 0b0000_1000;
+pub const BG_ATTR_X_FLIP: u8   = 0b0010_0000;
+pub const BG_ATTR_Y_FLIP: u8   = 0b0100_0000;
+pub const BG_ATTR_PRIORITY: u8 = 0b1000_0000;
+
+/* STAT bits */
+pub const STAT_COINCIDENCE: u8 = 0b0000_0100;
+pub const STAT_MODE0_INT: u8   = 0b0000_1000;
+pub const STAT_MODE1_INT: u8   = 0b0001_0000;
+pub const STAT_MODE2_INT: u8   = 0b0010_0000;
+pub const STAT_LYC_INT: u8     = 0b0100_0000;
+
+// DMG shades, from lightest to darkest (0xAARRGGBB)
+pub const DMG_COLORS: [u32; 4] = [0xFF9BBC0F, 0xFF8BAC0F, 0xFF306230, 0xFF0F380F];
+
+// Width and height of a frame: the LCD, or the SGB output with its border
+pub fn frame_size(frame: &[u32]) -> (usize, usize) {
+    if frame.len() == SGB_FB_LEN {(SGB_W, SGB_H)} else {(GB_W, GB_H)}
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum PpuBackend {
+    /// Draws each line at once, Mode 3 has a fixed length
+    #[default]
+    Scanline,
+
+    /// Dot accurate pixel FIFO, Mode 3 has a variable length
+    Fifo,
+}
+
+#[derive(Debug)]
 pub struct Ppu {
     frame: Frame,
+    backend: PpuBackend,
+
+    dots: u16,     // Current dot in the scanline (0..456)
+    ly: u8,        // Current scanline (0..154)
+    mode: Mode,    // Mode of the state machine, mirrored in STAT
+    lcd_on: bool,  // Was the LCD enabled during the last tick ?
+    stat_line: bool, // State of the internal STAT interrupt line
+
+    bg_line: [u8; GB_W], // BG/Window color indices of the current line
+    bg_attrs: [u8; GB_W], // CGB attributes of the BG/Window tiles of the current line
+    win_line: u8,        // Internal window line counter
+    wy_triggered: bool,  // Has LY matched WY during this frame ?
+    line_objects: Vec<Object>, // Objects selected by the OAM scan
+    obj_height: u8,            // Object height used by the OAM scan, even if LCDC changes later
+    fifo: PixelFifo,
 }
 
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq, Hash)]
@@ -18,17 +94,258 @@ pub enum Mode {
     Mode2 = 2,
     Mode3 = 3
 }
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new(PpuBackend::default())
+    }
+}
+
 impl Ppu {
-    pub fn new() -> Ppu {
+    pub fn new(backend: PpuBackend) -> Ppu {
         Ppu{
-            frame: vec![0u32; FB_LEN].into_boxed_slice()
+            frame: vec![0u32; FB_LEN].into_boxed_slice(),
+            backend,
+            dots: 0,
+            ly: 0,
+            mode: Mode::Mode0,
+            lcd_on: false,
+            stat_line: false,
+
+            bg_line: [0; GB_W],
+            bg_attrs: [0; GB_W],
+            win_line: 0,
+            wy_triggered: false,
+            line_objects: Vec::with_capacity(MAX_LINE_OBJECTS),
+            obj_height: 8,
+            fifo: PixelFifo::default(),
         }
     }
+
+    pub fn get_ly(&self) -> u8 {
+        self.ly
+    }
+
+    pub fn get_dots(&self) -> u16 {
+        self.dots
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn get_backend(&self) -> PpuBackend {
+        self.backend
+    }
+
+    pub fn get_line_objects(&self) -> &[Object] {
+        &self.line_objects
+    }
+
+    // Should be ticked every T cycle (one dot)
     pub fn tick<T>(&mut self, bus: &mut Bus, dbg: &mut T)
     where T: Debugger {
-        ()
+        if bus.ioregs[0x40] & LCDC_LCD_ENABLE == 0 {
+            if self.lcd_on {
+                self.disable_lcd(bus);
+            }
+            return;
+        }
+
+        if !self.lcd_on {
+            self.lcd_on = true;
+            self.dots = 0;
+            self.ly = 0;
+            self.start_frame();
+        } else {
+            self.dots += 1;
+            if self.dots == DOTS_PER_LINE {
+                self.dots = 0;
+                self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            }
+        }
+        bus.ioregs[0x44] = self.ly;
+
+        let mode = self.next_mode(bus);
+        if mode != self.mode {
+            self.mode = mode;
+            bus.set_ppu_mode(mode);
+            self.on_mode_change(bus);
+            dbg.on_ppu_event(DebugEvent::PpuModeChange(mode), self, bus);
+        }
+        self.update_coincidence(bus);
+        self.update_stat_line(bus);
     }
-    
+
+    // Mode the PPU should be in at the current scanline and dot
+    fn next_mode(&mut self, bus: &Bus) -> Mode {
+        match (self.ly, self.dots) {
+            (144.., _) => Mode::Mode1,
+            (_, 0..OAM_SCAN_DOTS) => Mode::Mode2,
+            (_, OAM_SCAN_DOTS) => Mode::Mode3,
+            _ if self.mode == Mode::Mode3 => {
+                let done = match self.backend {
+                    PpuBackend::Scanline => self.dots >= OAM_SCAN_DOTS + DRAW_DOTS,
+                    PpuBackend::Fifo => self.fifo_dot(bus),
+                };
+                if done {Mode::Mode0} else {Mode::Mode3}
+            },
+            _ => Mode::Mode0,
+        }
+    }
+
+    fn on_mode_change(&mut self, bus: &mut Bus) {
+        match self.mode {
+            Mode::Mode2 => {
+                if self.ly == 0 {
+                    self.start_frame();
+                }
+                if self.ly == bus.ioregs[0x4A] {
+                    self.wy_triggered = true;
+                }
+                self.oam_scan(bus);
+            },
+            Mode::Mode3 => match self.backend {
+                PpuBackend::Scanline => self.render_scanline(bus),
+                PpuBackend::Fifo => self.fifo_start_line(bus),
+            },
+            Mode::Mode0 => {
+                if self.backend == PpuBackend::Fifo {
+                    self.fifo_end_line();
+                }
+                bus.hblank_dma();
+            },
+            Mode::Mode1 => {
+                bus.set_interrupt(Interrupt::VBlank);
+                self.send_frame(bus);
+                bus.poll_compat_combo();
+                bus.sgb_vblank();
+            },
+        }
+    }
+
+    fn start_frame(&mut self) {
+        self.win_line = 0;
+        self.wy_triggered = false;
+    }
+
+    fn update_coincidence(&mut self, bus: &mut Bus) {
+        if self.ly == bus.ioregs[0x45] {
+            bus.ioregs[0x41] |= STAT_COINCIDENCE;
+        } else {
+            bus.ioregs[0x41] &= !STAT_COINCIDENCE;
+        }
+    }
+
+    // All STAT sources are ORed on a single line, and the LCD interrupt
+    // is only requested on its rising edge ("STAT blocking")
+    fn update_stat_line(&mut self, bus: &mut Bus) {
+        let stat = bus.ioregs[0x41];
+        let line = match self.mode {
+            Mode::Mode0 => stat & STAT_MODE0_INT != 0,
+            Mode::Mode1 => {
+                // The Mode 2 source also fires when entering VBlank
+                stat & STAT_MODE1_INT != 0
+                    || (stat & STAT_MODE2_INT != 0 && self.ly == 144 && self.dots == 0)
+            },
+            Mode::Mode2 => stat & STAT_MODE2_INT != 0,
+            Mode::Mode3 => false,
+        } || (stat & STAT_LYC_INT != 0 && stat & STAT_COINCIDENCE != 0);
+
+        if line && !self.stat_line {
+            bus.set_interrupt(Interrupt::LCD);
+        }
+        self.stat_line = line;
+    }
+
+    // When the LCD is turned off, LY is reset and STAT reports Mode 0
+    fn disable_lcd(&mut self, bus: &mut Bus) {
+        self.lcd_on = false;
+        self.dots = 0;
+        self.ly = 0;
+        self.mode = Mode::Mode0;
+        self.stat_line = false;
+        bus.ioregs[0x44] = 0;
+        bus.set_ppu_mode(Mode::Mode0);
+    }
+
+    // Address of a BG/Window tile, following the LCDC addressing mode
+    fn bg_tile_addr(lcdc: u8, tile: u8) -> u16 {
+        if lcdc & LCDC_TILE_DATA != 0 {
+            0x8000 + tile as u16 * 16
+        } else {
+            (0x9000i32 + (tile as i8) as i32 * 16) as u16
+        }
+    }
+
+    // Returns the (low, high) bitplanes of a row of a tile
+    fn read_tile_row(bus: &Bus, bank: usize, tile_addr: u16, row: u8) -> (u8, u8) {
+        let addr = tile_addr + row as u16 * 2;
+        (bus.ram.read_vram(bank, addr), bus.ram.read_vram(bank, addr + 1))
+    }
+
+    // CGB attributes of a BG/Window map entry. Always 0 on DMG
+    fn read_bg_attrs(bus: &Bus, map_addr: u16) -> u8 {
+        if bus.is_cgb() {bus.ram.read_vram(1, map_addr)} else {0}
+    }
+
+    // Row of a BG/Window tile, following the bank and vertical flip attributes
+    fn bg_tile_row(bus: &Bus, lcdc: u8, tile: u8, attrs: u8, row: u8) -> (u8, u8) {
+        let bank = if attrs & BG_ATTR_BANK != 0 {1} else {0};
+        let row = if attrs & BG_ATTR_Y_FLIP != 0 {7 - row} else {row};
+        Self::read_tile_row(bus, bank, Self::bg_tile_addr(lcdc, tile), row)
+    }
+
+    // Color index of a pixel in a tile row, 0 being the leftmost pixel
+    fn tile_pixel(lo: u8, hi: u8, x: u8) -> u8 {
+        let bit = 7 - x;
+        (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+    }
+
+    // Final color of a pixel from its BG color index and attributes, and the
+    // object pixel over it
+    fn mix_pixel(bus: &Bus, bg: u8, attrs: u8, obj: Option<(u8, u8)>) -> u32 {
+        if bus.is_cgb() {
+            return Self::mix_pixel_cgb(bus, bg, attrs, obj);
+        }
+
+        // The OBJ palette number, if the object is visible, and the shade
+        let (obj_palette, shade) = match obj {
+            Some((_, flags)) if flags & OBJ_PRIORITY != 0 && bg != 0 => (None, Self::apply_palette(bus.read(BGP), bg)),
+            Some((color, flags)) => {
+                let (palette, obp) = if flags & OBJ_PALETTE != 0 {(1, bus.read(OBP1))} else {(0, bus.read(OBP0))};
+                (Some(palette), Self::apply_palette(obp, color))
+            },
+            None => (None, Self::apply_palette(bus.read(BGP), bg)),
+        };
+
+        // In DMG compatibility mode, shades index the palettes set by the boot ROM.
+        // The SGB colorizes the shades when the frame is complete
+        match obj_palette {
+            _ if bus.is_sgb() => shade as u32,
+            _ if !bus.dmg_compat() => DMG_COLORS[shade as usize],
+            Some(palette) => bus.palettes.obj_rgba(palette, shade),
+            None => bus.palettes.bg_rgba(0, shade),
+        }
+    }
+
+    // On CGB, LCDC bit 0 is the BG master priority: when cleared, objects are
+    // always drawn over the BG and Window
+    fn mix_pixel_cgb(bus: &Bus, bg: u8, attrs: u8, obj: Option<(u8, u8)>) -> u32 {
+        let master_priority = bus.ioregs[0x40] & LCDC_BG_ENABLE != 0;
+        match obj {
+            Some((color, flags)) if !master_priority || bg == 0
+                || (flags & OBJ_PRIORITY == 0 && attrs & BG_ATTR_PRIORITY == 0) => {
+                bus.palettes.obj_rgba(flags & OBJ_CGB_PALETTE, color)
+            },
+            _ => bus.palettes.bg_rgba(attrs & BG_ATTR_PALETTE, bg),
+        }
+    }
+
+    fn apply_palette(palette: u8, color: u8) -> u8 {
+        (palette >> (color * 2)) & 0b11
+    }
+
     fn send_frame(&mut self, bus: &mut Bus) {
         let cur = std::mem::replace(&mut self.frame, vec![0u32; FB_LEN].into_boxed_slice());
         bus.send_frame(cur);
@@ -38,11 +355,6 @@ impl Ppu {
 impl Bus {
     fn set_ppu_mode(&mut self, mode: Mode) {
         let cur = self.ioregs[0x41] & 0b11111100;
-        match mode {
-            Mode::Mode0 => self.ioregs[0x41] = cur,
-            Mode::Mode1 => self.ioregs[0x41] = cur & 0b01,
-            Mode::Mode2 => self.ioregs[0x41] = cur & 0b10,
-            Mode::Mode3 => self.ioregs[0x41] = cur & 0b11,
-        }
+        self.ioregs[0x41] = cur | mode as u8;
     }
-}
\ No newline at end of file
+}
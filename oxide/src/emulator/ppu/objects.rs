@@ -0,0 +1,108 @@
+use super::*;
+use crate::emulator::memory::regdefines::*;
+
+/*
+ * Objects (sprites) handling.
+ * OAM holds 40 entries of 4 bytes: Y, X, tile index and attributes.
+ * The Mode 2 scan keeps at most 10 objects per line, in OAM order.
+ */
+
+pub const MAX_LINE_OBJECTS: usize = 10;
+
+/* Object attributes bits */
+pub const OBJ_CGB_PALETTE: u8 = 0b0000_0111;
+pub const OBJ_BANK: u8     = 0b0000_1000;
+pub const OBJ_PALETTE: u8  = 0b0001_0000;
+pub const OBJ_X_FLIP: u8   = 0b0010_0000;
+pub const OBJ_Y_FLIP: u8   = 0b0100_0000;
+pub const OBJ_PRIORITY: u8 = 0b1000_0000;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Object {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+    pub index: u8, // Position in OAM
+}
+
+impl Object {
+    fn from_oam(bus: &Bus, index: u8) -> Self {
+        let addr = 0xFE00 + index as u16 * 4;
+        Object {
+            y: bus.ram.read(addr),
+            x: bus.ram.read(addr + 1),
+            tile: bus.ram.read(addr + 2),
+            flags: bus.ram.read(addr + 3),
+            index,
+        }
+    }
+
+    // Does the object cover the pixel at screen column x ?
+    pub fn covers(&self, x: usize) -> bool {
+        let left = self.x as usize;
+        x + 8 >= left && x < left
+    }
+}
+
+impl Ppu {
+    // Mode 2: select the objects overlapping the current line
+    pub(super) fn oam_scan(&mut self, bus: &Bus) {
+        let height = Self::obj_height(bus.read(LCDC));
+        self.obj_height = height;
+        let line = self.ly as u16 + 16;
+
+        self.line_objects.clear();
+        for index in 0..40 {
+            let obj = Object::from_oam(bus, index);
+            if line >= obj.y as u16 && line < obj.y as u16 + height as u16 {
+                self.line_objects.push(obj);
+                if self.line_objects.len() == MAX_LINE_OBJECTS {
+                    break;
+                }
+            }
+        }
+
+        // Fetch order. This is also the DMG priority: the smallest X is drawn
+        // on top, OAM order breaks ties
+        self.line_objects.sort_by_key(|o| (o.x, o.index));
+    }
+
+    // Returns the color index and attributes of the object pixel at column x
+    pub(super) fn object_pixel(&self, bus: &Bus, x: usize) -> Option<(u8, u8)> {
+        let mut pixels = self.line_objects.iter().filter(|o| o.covers(x)).filter_map(|obj| {
+            let (lo, hi) = Self::object_row(bus, obj, self.ly, self.obj_height);
+            let mut px = (x + 8 - obj.x as usize) as u8;
+            if obj.flags & OBJ_X_FLIP != 0 {
+                px = 7 - px;
+            }
+
+            let color = Self::tile_pixel(lo, hi, px);
+            (color != 0).then_some((obj.index, color, obj.flags))
+        });
+
+        // CGB priority: the first object in OAM is drawn on top
+        let pixel = if bus.oam_priority() {pixels.min_by_key(|p| p.0)} else {pixels.next()};
+        pixel.map(|(_, color, flags)| (color, flags))
+    }
+
+    // Fetches the bitplanes of the object row displayed on line ly
+    pub(super) fn object_row(bus: &Bus, obj: &Object, ly: u8, height: u8) -> (u8, u8) {
+        let mut row = ly.wrapping_add(16).wrapping_sub(obj.y);
+        if obj.flags & OBJ_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+
+        let tile = if height == 16 {obj.tile & 0xFE} else {obj.tile};
+        let bank = if bus.is_cgb() && obj.flags & OBJ_BANK != 0 {1} else {0};
+        Self::read_tile_row(bus, bank, 0x8000 + tile as u16 * 16, row)
+    }
+
+    pub(super) fn obj_height(lcdc: u8) -> u8 {
+        if lcdc & LCDC_OBJ_SIZE != 0 {16} else {8}
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/objects.rs"]
+mod objects_tests;
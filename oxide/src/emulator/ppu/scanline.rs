@@ -0,0 +1,66 @@
+use super::*;
+
+/*
+ * Scanline renderer.
+ * The whole line is drawn at once when the PPU enters Mode 3, using the
+ * register values at that time. Mid-scanline register writes are not visible.
+ */
+
+impl Ppu {
+    pub(super) fn render_scanline(&mut self, bus: &Bus) {
+        let lcdc = bus.read(LCDC);
+
+        self.bg_line = [0; GB_W];
+        self.bg_attrs = [0; GB_W];
+        // On CGB, LCDC bit 0 only affects priority and the BG is always drawn
+        if lcdc & LCDC_BG_ENABLE != 0 || bus.is_cgb() {
+            self.render_background(bus, lcdc);
+            if lcdc & LCDC_WIN_ENABLE != 0 {
+                self.render_window(bus, lcdc);
+            }
+        }
+
+        let obj_enabled = lcdc & LCDC_OBJ_ENABLE != 0;
+        let row = self.ly as usize * GB_W;
+        for x in 0..GB_W {
+            let obj = if obj_enabled {self.object_pixel(bus, x)} else {None};
+            self.frame[row + x] = Self::mix_pixel(bus, self.bg_line[x], self.bg_attrs[x], obj);
+        }
+    }
+
+    fn render_background(&mut self, bus: &Bus, lcdc: u8) {
+        let map = if lcdc & LCDC_BG_MAP != 0 {0x9C00} else {0x9800};
+        let y = self.ly.wrapping_add(bus.read(SCY));
+        let scx = bus.read(SCX);
+
+        for x in 0..GB_W {
+            let map_x = (x as u8).wrapping_add(scx);
+            (self.bg_line[x], self.bg_attrs[x]) = Self::map_pixel(bus, lcdc, map, map_x, y);
+        }
+    }
+
+    fn render_window(&mut self, bus: &Bus, lcdc: u8) {
+        let wx = bus.read(WX);
+        if !self.wy_triggered || wx > 166 {
+            return;
+        }
+
+        let map = if lcdc & LCDC_WIN_MAP != 0 {0x9C00} else {0x9800};
+        let start = wx.saturating_sub(7) as usize;
+        for x in start..GB_W {
+            let win_x = (x + 7 - wx as usize) as u8;
+            (self.bg_line[x], self.bg_attrs[x]) = Self::map_pixel(bus, lcdc, map, win_x, self.win_line);
+        }
+        self.win_line += 1;
+    }
+
+    // Color index and attributes of the pixel at (x, y) of a 256x256 tile map
+    fn map_pixel(bus: &Bus, lcdc: u8, map: u16, x: u8, y: u8) -> (u8, u8) {
+        let map_addr = map + (y as u16 / 8) * 32 + (x as u16 / 8);
+        let tile = bus.ram.read_vram(0, map_addr);
+        let attrs = Self::read_bg_attrs(bus, map_addr);
+        let (lo, hi) = Self::bg_tile_row(bus, lcdc, tile, attrs, y % 8);
+        let px = if attrs & BG_ATTR_X_FLIP != 0 {7 - x % 8} else {x % 8};
+        (Self::tile_pixel(lo, hi, px), attrs)
+    }
+}
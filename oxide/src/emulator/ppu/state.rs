@@ -0,0 +1,64 @@
+use super::*;
+use crate::emulator::state::*;
+
+const MODES: [Mode; 4] = [Mode::Mode0, Mode::Mode1, Mode::Mode2, Mode::Mode3];
+
+// The backend is a setting of the emulator, not part of the state
+impl SaveState for Ppu {
+    fn save_state(&self, w: &mut StateWriter) {
+        for &pixel in self.frame.iter() {
+            w.u32(pixel);
+        }
+        w.u16(self.dots);
+        w.u8(self.ly);
+        w.u8(self.mode as u8);
+        w.bool(self.lcd_on);
+        w.bool(self.stat_line);
+
+        w.bytes(&self.bg_line);
+        w.bytes(&self.bg_attrs);
+        w.u8(self.win_line);
+        w.bool(self.wy_triggered);
+        w.u8(self.obj_height);
+        w.u8(self.line_objects.len() as u8);
+        for obj in &self.line_objects {
+            for value in [obj.y, obj.x, obj.tile, obj.flags, obj.index] {
+                w.u8(value);
+            }
+        }
+        self.fifo.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for pixel in self.frame.iter_mut() {
+            *pixel = r.u32()?;
+        }
+        self.dots = r.u16()?;
+        self.ly = r.u8()?;
+        let mode = r.u8()?;
+        self.mode = *MODES.get(mode as usize).ok_or_else(|| invalid_tag("PPU mode", mode))?;
+        self.lcd_on = r.bool()?;
+        self.stat_line = r.bool()?;
+
+        r.bytes_into(&mut self.bg_line)?;
+        r.bytes_into(&mut self.bg_attrs)?;
+        self.win_line = r.u8()?;
+        self.wy_triggered = r.bool()?;
+        self.obj_height = match r.u8()? {
+            height @ (8 | 16) => height,
+            height => return Err(invalid_tag("object height", height)),
+        };
+        let count = r.u8()?;
+        self.line_objects.clear();
+        for _ in 0..count {
+            self.line_objects.push(Object {
+                y: r.u8()?,
+                x: r.u8()?,
+                tile: r.u8()?,
+                flags: r.u8()?,
+                index: r.u8()?,
+            });
+        }
+        self.fifo.load_state(r)
+    }
+}
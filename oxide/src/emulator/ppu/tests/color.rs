@@ -0,0 +1,41 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::ppu::color::*;
+
+    #[test]
+    fn test_palette_auto_increment() {
+        let mut ram = PaletteRam::default();
+        ram.write_spec(0x80 | 0x3E);
+        assert_eq!(ram.read_spec(), 0xFE, "Bit 6 reads 1");
+
+        ram.write_data(0x1F, false);
+        ram.write_data(0x7C, false);
+        assert_eq!(ram.read_spec(), 0xC0, "Index wraps around");
+        assert_eq!(ram.color(7, 3), 0x7C1F);
+
+        ram.write_data(0x12, true);
+        assert_eq!(ram.read_spec(), 0xC1, "Blocked writes still increment");
+        ram.write_spec(0x00);
+        assert_eq!(ram.read_data(), 0xFF, "Blocked write ignored");
+
+        ram.write_data(0x34, false);
+        assert_eq!(ram.read_spec(), 0x40, "No increment without bit 7");
+        assert_eq!(ram.read_data(), 0x34);
+    }
+
+    #[test]
+    fn test_rgb555_to_rgba() {
+        assert_eq!(rgb555_to_rgba(0x0000, false), 0xFF000000);
+        assert_eq!(rgb555_to_rgba(0x7FFF, false), 0xFFFFFFFF);
+        assert_eq!(rgb555_to_rgba(0x001F, false), 0xFFFF0000, "Red");
+        assert_eq!(rgb555_to_rgba(0x03E0, false), 0xFF00FF00, "Green");
+        assert_eq!(rgb555_to_rgba(0x7C00, false), 0xFF0000FF, "Blue");
+        assert_eq!(rgb555_to_rgba(0x0010, false), 0xFF840000);
+
+        assert_eq!(rgb555_to_rgba(0x0000, true), 0xFF000000);
+        assert_eq!(rgb555_to_rgba(0x7FFF, true), 0xFFF0F0F0, "White is dimmed");
+        let red = rgb555_to_rgba(0x001F, true);
+        assert!(red & 0xFF > 0 && red & 0xFF00 == 0, "Red bleeds into blue");
+    }
+}
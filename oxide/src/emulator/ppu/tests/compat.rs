@@ -0,0 +1,54 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::memory::cartridge::header::*;
+    use crate::emulator::ppu::color::*;
+    use crate::emulator::ppu::compat::*;
+
+    fn make_header(title: &[u8], licensee: u8) -> CartridgeHeader {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom[0x14B] = licensee;
+        CartridgeHeader::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn test_title_combination() {
+        assert_eq!(title_combination(&make_header(b"POKEMON RED", 0x01)), 13, "Checksum match");
+        assert_eq!(title_combination(&make_header(b"POKEMON RED", 0x08)), 0, "Not a Nintendo title");
+        assert_eq!(title_combination(&make_header(b"TETRIS", 0x01)), 3, "Tetris");
+        assert_eq!(title_combination(&make_header(b"UNKNOWN", 0x01)), 0, "Unknown title");
+
+        // POKEMON BLUE shares its checksum with other titles
+        assert_eq!(title_combination(&make_header(b"POKEMON BLUE", 0x01)), 11, "4th letter match");
+        let mut header = make_header(b"POKEMON BLUE", 0x01);
+        // Same checksum, different 4th letter
+        header.raw_title[3] = b'X';
+        header.raw_title[4] -= b'X' - b'E';
+        assert_eq!(header.title_checksum(), make_header(b"POKEMON BLUE", 0x01).title_checksum());
+        assert_eq!(title_combination(&header), 0, "4th letter mismatch");
+    }
+
+    #[test]
+    fn test_key_combination() {
+        assert_eq!(key_combination(0x00), None, "No buttons");
+        assert_eq!(key_combination(0x20), Some(48), "Left");
+        assert_eq!(key_combination(0x21), Some(40), "Left + A");
+        assert_eq!(key_combination(0x28), Some(48), "Start is ignored");
+        assert_eq!(key_combination(0x30), None, "Two directions");
+        assert_eq!(key_combination(0x01), None, "No direction");
+    }
+
+    #[test]
+    fn test_load_combination() {
+        let mut palettes = CgbPalettes::new(false);
+        palettes.load_combination(3);
+        assert_eq!(palettes.bg_rgba(0, 1), 0xFFFFFF00, "Tetris yellow");
+        assert_eq!(palettes.obj_rgba(1, 2), 0xFFFF0000, "Tetris red");
+
+        // Combination starting in the middle of a color set
+        palettes.load_combination(22);
+        assert_eq!(palettes.obj_rgba(0, 0), 0xFF000000);
+        assert_eq!(palettes.obj_rgba(0, 1), 0xFFFFFFFF);
+    }
+}
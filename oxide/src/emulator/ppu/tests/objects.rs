@@ -0,0 +1,70 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::DummyDebugger;
+    use crate::emulator::internals::iomanager::IoManager;
+    use crate::emulator::memory::cartridge::header::*;
+    use crate::emulator::ppu::*;
+    use crate::emulator::Model;
+    use crate::settings::{Settings, GLOB_SETTINGS};
+    use crossbeam_channel::bounded;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, AtomicU8};
+    use std::sync::Arc;
+
+    fn make_bus(name: &str) -> Bus {
+        GLOB_SETTINGS.get_or_init(|| Arc::new(Settings { sample_rate: 48000, ..Default::default() }));
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+        let path = std::env::temp_dir().join(format!("oxide_ppu_{}_{name}.gb", std::process::id()));
+        std::fs::write(&path, rom).unwrap();
+
+        let (tx_frame, _) = bounded(1);
+        let io_manager = IoManager::new(tx_frame, Arc::new(AtomicU8::new(0)), Arc::new(AtomicBool::new(false)), false);
+        let bus = Bus::new(path.clone(), PathBuf::new(), io_manager, Model::Dmg).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bus
+    }
+
+    // Draws line 8 with a Y-flipped 8x16 object, switching to 8x8 objects
+    // after the OAM scan
+    fn check_size_change(backend: PpuBackend) {
+        let mut bus = make_bus(&format!("{backend:?}"));
+        let mut ppu = Ppu::new(backend);
+        let mut dbg = DummyDebugger::default();
+
+        // Row 7 of the top tile is color 3
+        bus.ram.write(0x8000 + 7 * 2, 0xFF);
+        bus.ram.write(0x8000 + 7 * 2 + 1, 0xFF);
+        bus.ram.write(0xFE00, 16);
+        bus.ram.write(0xFE01, 8);
+        bus.ram.write(0xFE02, 0);
+        bus.ram.write(0xFE03, OBJ_Y_FLIP);
+        bus.ioregs[0x48] = 0xE4;
+        bus.ioregs[0x40] = LCDC_LCD_ENABLE | LCDC_OBJ_ENABLE | LCDC_OBJ_SIZE;
+
+        while !(ppu.ly == 8 && ppu.dots == OAM_SCAN_DOTS - 1) {
+            ppu.tick(&mut bus, &mut dbg);
+        }
+        bus.ioregs[0x40] &= !LCDC_OBJ_SIZE;
+        while !(ppu.ly == 8 && ppu.mode == Mode::Mode0) {
+            ppu.tick(&mut bus, &mut dbg);
+        }
+
+        let line = &ppu.frame[8 * GB_W..9 * GB_W];
+        assert_ne!(line[0], line[8], "{backend:?}: row 8 flipped to row 7 of the 8x16 object");
+        assert_eq!(ppu.frame[0], line[8], "{backend:?}: row 0 flipped to row 15 of the 8x16 object");
+    }
+
+    #[test]
+    fn test_size_change_scanline() {
+        check_size_change(PpuBackend::Scanline);
+    }
+
+    #[test]
+    fn test_size_change_fifo() {
+        check_size_change(PpuBackend::Fifo);
+    }
+}
@@ -0,0 +1,113 @@
+use super::{SGB_FB_LEN, SGB_W};
+use crate::emulator::ppu::color::rgb555_to_rgba;
+use crate::emulator::ppu::Frame;
+use crate::emulator::state::*;
+
+/*
+ * SGB border.
+ * CHR_TRN loads 256 SNES tiles (4 bits per pixel, 32 bytes each) in two
+ * halves, PCT_TRN loads the 32x32 tile map and the border palettes 4-7.
+ * Map entries hold the tile number, the palette and the flip bits. Color 0
+ * is transparent and shows the backdrop, which is SGB color 0.
+ */
+
+const TILE_LEN: usize = 32;
+const MAP_W: usize = 32;
+const MAP_H: usize = 28;  // Visible rows of the 32x32 map
+const MAP_LEN: usize = 0x800;
+const PALETTES_LEN: usize = 0x80;
+
+/* Map entries bits */
+const MAP_TILE: u16 = 0x00FF;
+const MAP_X_FLIP: u16 = 0x4000;
+const MAP_Y_FLIP: u16 = 0x8000;
+
+pub fn to_rgba(color: u16) -> u32 {
+    rgb555_to_rgba(color, false)
+}
+
+pub struct Border {
+    tiles: Vec<u8>,
+    map: Vec<u16>,
+    palettes: [[u16; 16]; 4],
+}
+
+impl Default for Border {
+    fn default() -> Self {
+        Border {
+            tiles: vec![0; 256 * TILE_LEN],
+            map: vec![0; MAP_W * MAP_W],
+            palettes: [[0; 16]; 4],
+        }
+    }
+}
+
+impl SaveState for Border {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.tiles);
+        for &entry in self.map.iter().chain(self.palettes.as_flattened()) {
+            w.u16(entry);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.tiles)?;
+        for entry in self.map.iter_mut().chain(self.palettes.as_flattened_mut()) {
+            *entry = r.u16()?;
+        }
+        Ok(())
+    }
+}
+
+impl Border {
+    pub fn load_tiles(&mut self, data: &[u8], high: bool) {
+        let start = if high {128 * TILE_LEN} else {0};
+        self.tiles[start..start + 128 * TILE_LEN].copy_from_slice(&data[..128 * TILE_LEN]);
+    }
+
+    pub fn load_picture(&mut self, data: &[u8]) {
+        for (entry, bytes) in self.map.iter_mut().zip(data[..MAP_LEN].chunks_exact(2)) {
+            *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        let colors = data[MAP_LEN..MAP_LEN + PALETTES_LEN].chunks_exact(2);
+        for (i, bytes) in colors.enumerate() {
+            self.palettes[i / 16][i % 16] = u16::from_le_bytes([bytes[0], bytes[1]]) & 0x7FFF;
+        }
+    }
+
+    // Color index of a pixel of a tile, in SNES planar format
+    fn tile_pixel(&self, tile: usize, x: usize, y: usize) -> usize {
+        let data = &self.tiles[tile * TILE_LEN..(tile + 1) * TILE_LEN];
+        let bit = 7 - x;
+        let planes = [data[y * 2], data[y * 2 + 1], data[16 + y * 2], data[16 + y * 2 + 1]];
+        planes.iter().enumerate()
+            .map(|(i, plane)| (((plane >> bit) & 1) as usize) << i)
+            .sum()
+    }
+
+    pub fn render(&self, backdrop: u16) -> Frame {
+        let mut frame = vec![to_rgba(backdrop); SGB_FB_LEN].into_boxed_slice();
+
+        for ty in 0..MAP_H {
+            for tx in 0..MAP_W {
+                let entry = self.map[ty * MAP_W + tx];
+                let tile = (entry & MAP_TILE) as usize;
+                // Palettes 4 to 7
+                let palette = &self.palettes[((entry >> 10) & 0b11) as usize];
+
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let px = if entry & MAP_X_FLIP != 0 {7 - x} else {x};
+                        let py = if entry & MAP_Y_FLIP != 0 {7 - y} else {y};
+                        let color = self.tile_pixel(tile, px, py);
+                        if color != 0 {
+                            frame[(ty * 8 + y) * SGB_W + tx * 8 + x] = to_rgba(palette[color]);
+                        }
+                    }
+                }
+            }
+        }
+        frame
+    }
+}
@@ -0,0 +1,405 @@
+pub mod border;
+
+use crate::emulator::ppu::{Frame, GB_H, GB_W};
+use crate::emulator::state::*;
+use border::Border;
+use log::{debug, info, warn};
+
+/*
+ * Super Game Boy.
+ * The cartridge talks to the SGB through the JOYP register: packets of 16
+ * bytes are sent bit by bit, a reset pulse (P14 and P15 low) starting each
+ * packet. The low 3 bits of the first byte give the number of packets of the
+ * command, the high 5 bits the command itself.
+ * Some commands transfer 4 KiB of data through the Game Boy screen, which
+ * is read from VRAM on the next VBlank (see Bus::sgb_vblank).
+ * The SGB colorizes the 4 DMG shades with 4 palettes, chosen for each 8x8
+ * cell of the screen, and draws a 256x224 border around it.
+ */
+
+pub const SGB_W: usize = 256;
+pub const SGB_H: usize = 224;
+pub const SGB_FB_LEN: usize = SGB_W * SGB_H;
+
+// Position of the Game Boy screen in the border
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+const PACKET_LEN: usize = 16;
+const PACKET_BITS: usize = PACKET_LEN * 8;
+pub const TRANSFER_LEN: usize = 0x1000;
+
+// Palette attributes are set for each 8x8 cell
+const CELLS_W: usize = GB_W / 8;
+const CELLS_H: usize = GB_H / 8;
+
+/* Commands */
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Transfer {
+    Palettes,
+    Tiles(bool), // Second half of the border tiles ?
+    Picture,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum Mask {
+    #[default]
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+pub struct Sgb {
+    // Packet reception
+    receiving: bool,
+    bits: usize,                    // Bits received in the current packet
+    packet: [u8; PACKET_LEN],
+    command: Vec<u8>,               // Packets of the current command
+    last_sel: u8,                   // P14 and P15 on the last JOYP write
+
+    players: u8,                    // 1, 2 or 4 with MLT_REQ
+    player: u8,                     // Joypad read through JOYP
+
+    palettes: [[u16; 4]; 4],        // Color 0 of palette 0 is shared by all
+    system_palettes: Vec<[u16; 4]>, // 512 palettes, loaded by PAL_TRN
+    attrs: [u8; CELLS_W * CELLS_H], // Palette of each cell
+    mask: Mask,
+    transfer: Option<Transfer>,
+    screen: Vec<u8>,                // Last shades displayed, kept while frozen
+    border: Border,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Sgb {
+            receiving: false,
+            bits: 0,
+            packet: [0; PACKET_LEN],
+            command: Vec::new(),
+            last_sel: 0x30,
+
+            players: 1,
+            player: 0,
+
+            // Shades of gray until the cartridge sets its palettes
+            palettes: [[0x7FFF, 0x56B5, 0x294A, 0x0000]; 4],
+            system_palettes: vec![[0; 4]; 512],
+            attrs: [0; CELLS_W * CELLS_H],
+            mask: Mask::Cancel,
+            transfer: None,
+            screen: vec![0; GB_W * GB_H],
+            border: Border::default(),
+        }
+    }
+}
+
+impl Sgb {
+    // Decodes the packet bits from the P14 and P15 lines
+    pub fn write_joyp(&mut self, value: u8) {
+        let sel = value & 0x30;
+        let last = std::mem::replace(&mut self.last_sel, sel);
+
+        match sel {
+            // Reset pulse
+            0x00 => {
+                self.receiving = true;
+                self.bits = 0;
+                self.packet = [0; PACKET_LEN];
+            },
+            // A bit is sent by pulling P14 (0) or P15 (1) low
+            0x10 | 0x20 if self.receiving && last == 0x30 && self.bits <= PACKET_BITS => {
+                if sel == 0x10 && self.bits < PACKET_BITS {
+                    self.packet[self.bits / 8] |= 1 << (self.bits % 8);
+                }
+                self.bits += 1;
+            },
+            // The packet ends with a stop bit
+            0x30 if self.receiving && self.bits > PACKET_BITS => {
+                self.receiving = false;
+                self.receive_packet();
+            },
+            // The next joypad is selected when P15 goes back high
+            _ if !self.receiving && sel & 0x20 != 0 && last & 0x20 == 0 && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            },
+            _ => (),
+        }
+    }
+
+    // Lower nibble of JOYP when no line is selected: the current joypad ID
+    pub fn joypad_id(&self) -> Option<u8> {
+        (self.players > 1).then_some(0xF - self.player)
+    }
+
+    // Only the first joypad is connected
+    pub fn first_player(&self) -> bool {
+        self.player == 0
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+
+        let packets = (self.command[0] & 0b111).max(1) as usize;
+        if self.command.len() >= packets * PACKET_LEN {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        let command = data[0] >> 3;
+        debug!("SGB command {command:#04X}");
+
+        match command {
+            PAL01 => self.set_palette_pair(0, 1, data),
+            PAL23 => self.set_palette_pair(2, 3, data),
+            PAL03 => self.set_palette_pair(0, 3, data),
+            PAL12 => self.set_palette_pair(1, 2, data),
+            ATTR_BLK => self.attr_blk(data),
+            ATTR_LIN => self.attr_lin(data),
+            PAL_SET => self.pal_set(data),
+            PAL_TRN => self.transfer = Some(Transfer::Palettes),
+            CHR_TRN => self.transfer = Some(Transfer::Tiles(data[1] & 1 != 0)),
+            PCT_TRN => self.transfer = Some(Transfer::Picture),
+            MLT_REQ => {
+                self.players = match data[1] & 0b11 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            },
+            MASK_EN => {
+                self.mask = match data[1] & 0b11 {
+                    0 => Mask::Cancel,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                };
+            },
+            _ => info!("Unsupported SGB command {command:#04X}"),
+        }
+    }
+
+    fn color(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]]) & 0x7FFF
+    }
+
+    // Color 0, then colors 1-3 of both palettes
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        self.palettes[0][0] = Self::color(data, 1);
+        for color in 1..4 {
+            self.palettes[first][color] = Self::color(data, 1 + color * 2);
+            self.palettes[second][color] = Self::color(data, 7 + color * 2);
+        }
+    }
+
+    // Sets the palettes of the cells inside, on and outside of rectangles
+    fn attr_blk(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+
+        for set in data[2..].chunks_exact(6).take(count) {
+            let (control, palettes) = (set[0] & 0b111, set[1]);
+            let (x1, y1, x2, y2) = (set[2] as usize, set[3] as usize, set[4] as usize, set[5] as usize);
+            let inside = palettes & 0b11;
+            let outside = (palettes >> 4) & 0b11;
+            // When only the inside or the outside is set, the border follows it
+            let (border, set_border) = match control {
+                0b001 => (inside, true),
+                0b100 => (outside, true),
+                _ => ((palettes >> 2) & 0b11, control & 0b010 != 0),
+            };
+
+            for y in 0..CELLS_H {
+                for x in 0..CELLS_W {
+                    let in_x = (x1..=x2).contains(&x);
+                    let in_y = (y1..=y2).contains(&y);
+                    let on_edge = (in_x && (y == y1 || y == y2)) || (in_y && (x == x1 || x == x2));
+                    let cell = &mut self.attrs[y * CELLS_W + x];
+
+                    if on_edge {
+                        if set_border {
+                            *cell = border;
+                        }
+                    } else if in_x && in_y {
+                        if control & 0b001 != 0 {
+                            *cell = inside;
+                        }
+                    } else if control & 0b100 != 0 {
+                        *cell = outside;
+                    }
+                }
+            }
+        }
+    }
+
+    // Sets the palette of whole rows or columns of cells
+    fn attr_lin(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+
+        for &line in data[2..].iter().take(count) {
+            let index = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0b11;
+            if line & 0x80 != 0 {
+                if index < CELLS_H {
+                    self.attrs[index * CELLS_W..(index + 1) * CELLS_W].fill(palette);
+                }
+            } else if index < CELLS_W {
+                for y in 0..CELLS_H {
+                    self.attrs[y * CELLS_W + index] = palette;
+                }
+            }
+        }
+    }
+
+    // Copies 4 of the palettes received by PAL_TRN
+    fn pal_set(&mut self, data: &[u8]) {
+        for i in 0..4 {
+            let index = (Self::color(data, 1 + i * 2) & 0x1FF) as usize;
+            self.palettes[i] = self.system_palettes[index];
+        }
+
+        if data[9] & 0x80 != 0 {
+            warn!("SGB attribute files are not supported");
+        }
+        if data[9] & 0x40 != 0 {
+            self.mask = Mask::Cancel;
+        }
+    }
+
+    pub fn transfer_pending(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // Called with the 4 KiB displayed on the screen after a *_TRN command
+    pub fn finish_transfer(&mut self, data: &[u8]) {
+        match self.transfer.take() {
+            Some(Transfer::Palettes) => {
+                for (palette, colors) in self.system_palettes.iter_mut().zip(data.chunks_exact(8)) {
+                    for (i, color) in palette.iter_mut().enumerate() {
+                        *color = Self::color(colors, i * 2);
+                    }
+                }
+            },
+            Some(Transfer::Tiles(high)) => self.border.load_tiles(data, high),
+            Some(Transfer::Picture) => self.border.load_picture(data),
+            None => (),
+        }
+    }
+
+    // Builds the 256x224 output from the shades drawn by the PPU
+    pub fn compose(&mut self, shades: &[u32]) -> Frame {
+        if self.mask != Mask::Freeze {
+            for (dst, src) in self.screen.iter_mut().zip(shades) {
+                *dst = *src as u8 & 0b11;
+            }
+        }
+
+        let backdrop = self.palettes[0][0];
+        let mut frame = self.border.render(backdrop);
+
+        for y in 0..GB_H {
+            for x in 0..GB_W {
+                let color = match self.mask {
+                    Mask::Black => 0x0000,
+                    Mask::Color0 => backdrop,
+                    _ => {
+                        let shade = self.screen[y * GB_W + x] as usize;
+                        let palette = self.attrs[(y / 8) * CELLS_W + x / 8] as usize;
+                        if shade == 0 {backdrop} else {self.palettes[palette][shade]}
+                    },
+                };
+                frame[(y + SCREEN_Y) * SGB_W + x + SCREEN_X] = border::to_rgba(color);
+            }
+        }
+        frame
+    }
+}
+
+impl SaveState for Sgb {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.receiving);
+        w.u8(self.bits as u8);
+        w.bytes(&self.packet);
+        w.bytes(&self.command);
+        w.u8(self.last_sel);
+
+        w.u8(self.players);
+        w.u8(self.player);
+
+        for &color in self.palettes.as_flattened().iter().chain(self.system_palettes.as_flattened()) {
+            w.u16(color);
+        }
+        w.bytes(&self.attrs);
+        w.u8(self.mask as u8);
+        w.u8(match self.transfer {
+            None => 0,
+            Some(Transfer::Palettes) => 1,
+            Some(Transfer::Tiles(false)) => 2,
+            Some(Transfer::Tiles(true)) => 3,
+            Some(Transfer::Picture) => 4,
+        });
+        w.bytes(&self.screen);
+        self.border.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.receiving = r.bool()?;
+        self.bits = r.u8()? as usize;
+        if self.bits > PACKET_BITS + 1 {
+            return Err(invalid_tag("SGB packet bit count", self.bits as u8));
+        }
+        r.bytes_into(&mut self.packet)?;
+        self.command = r.bytes()?.to_vec();
+        self.last_sel = r.u8()?;
+
+        self.players = match r.u8()? {
+            players @ (1 | 2 | 4) => players,
+            tag => return Err(invalid_tag("SGB player count", tag)),
+        };
+        self.player = match r.u8()? {
+            player if player < self.players => player,
+            tag => return Err(invalid_tag("SGB player", tag)),
+        };
+
+        for color in self.palettes.as_flattened_mut().iter_mut().chain(self.system_palettes.as_flattened_mut()) {
+            *color = r.u16()?;
+        }
+        r.bytes_into(&mut self.attrs)?;
+        self.mask = match r.u8()? {
+            0 => Mask::Cancel,
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            tag => return Err(invalid_tag("SGB mask", tag)),
+        };
+        self.transfer = match r.u8()? {
+            0 => None,
+            1 => Some(Transfer::Palettes),
+            2 => Some(Transfer::Tiles(false)),
+            3 => Some(Transfer::Tiles(true)),
+            4 => Some(Transfer::Picture),
+            tag => return Err(invalid_tag("SGB transfer", tag)),
+        };
+        r.bytes_into(&mut self.screen)?;
+        self.border.load_state(r)
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/sgb.rs"]
+mod sgb_tests;
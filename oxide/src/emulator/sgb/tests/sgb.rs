@@ -0,0 +1,185 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::ppu::{FB_LEN, GB_W};
+    use crate::emulator::sgb::*;
+    use crate::emulator::state::*;
+
+    // Sends packets through JOYP, as the cartridge would
+    fn send(sgb: &mut Sgb, data: &[u8]) {
+        for packet in data.chunks(16) {
+            sgb.write_joyp(0x00);
+            sgb.write_joyp(0x30);
+            for i in 0..128 {
+                let bit = packet.get(i / 8).is_some_and(|b| b >> (i % 8) & 1 != 0);
+                sgb.write_joyp(if bit {0x10} else {0x20});
+                sgb.write_joyp(0x30);
+            }
+            sgb.write_joyp(0x20);
+            sgb.write_joyp(0x30);
+        }
+    }
+
+    fn screen_pixel(frame: &Frame, x: usize, y: usize) -> u32 {
+        frame[(y + 40) * SGB_W + x + 48]
+    }
+
+    #[test]
+    fn test_pal01() {
+        let mut sgb = Sgb::default();
+        let mut packet = [0u8; 16];
+        packet[0] = (PAL01 << 3) | 1;
+        packet[1..3].copy_from_slice(&0x001Fu16.to_le_bytes());   // Red
+        packet[3..5].copy_from_slice(&0x03E0u16.to_le_bytes());   // Green
+        packet[9..11].copy_from_slice(&0x7C00u16.to_le_bytes());  // Blue
+        send(&mut sgb, &packet);
+
+        let mut shades = vec![1u32; FB_LEN];
+        shades[1] = 0;
+        let frame = sgb.compose(&shades);
+        assert_eq!(frame.len(), SGB_FB_LEN);
+        assert_eq!(screen_pixel(&frame, 0, 0), 0xFF00FF00, "Palette 0 color 1");
+        assert_eq!(screen_pixel(&frame, 1, 0), 0xFFFF0000, "Shared color 0");
+        assert_eq!(frame[0], 0xFFFF0000, "Backdrop");
+    }
+
+    #[test]
+    fn test_packet_split() {
+        let mut sgb = Sgb::default();
+        // A partial command does nothing until the last packet
+        let mut data = [0u8; 32];
+        data[0] = (MLT_REQ << 3) | 2;
+        data[1] = 1;
+        send(&mut sgb, &data[..16]);
+        assert_eq!(sgb.joypad_id(), None);
+        send(&mut sgb, &data[16..]);
+        assert_eq!(sgb.joypad_id(), Some(0xF), "First joypad");
+
+        sgb.write_joyp(0x10);
+        sgb.write_joyp(0x30);
+        assert_eq!(sgb.joypad_id(), Some(0xE), "Second joypad");
+        assert!(!sgb.first_player());
+        sgb.write_joyp(0x10);
+        sgb.write_joyp(0x30);
+        assert_eq!(sgb.joypad_id(), Some(0xF), "Back to the first joypad");
+    }
+
+    #[test]
+    fn test_joypad_reads() {
+        let mut sgb = Sgb::default();
+        let mut packet = [0u8; 16];
+        packet[0] = (MLT_REQ << 3) | 1;
+        packet[1] = 3;
+        send(&mut sgb, &packet);
+
+        // Reading the D-Pad then the buttons selects the next joypad
+        for id in [0xE, 0xD, 0xC, 0xF] {
+            sgb.write_joyp(0x20);
+            sgb.write_joyp(0x10);
+            sgb.write_joyp(0x30);
+            assert_eq!(sgb.joypad_id(), Some(id));
+        }
+        // P14 going high alone does not
+        sgb.write_joyp(0x20);
+        sgb.write_joyp(0x30);
+        assert_eq!(sgb.joypad_id(), Some(0xF));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut sgb = Sgb::default();
+        let mut packet = [0u8; 16];
+        packet[0] = (PAL23 << 3) | 1;
+        packet[3..5].copy_from_slice(&0x001Fu16.to_le_bytes());
+        packet[9..11].copy_from_slice(&0x7C00u16.to_le_bytes());
+        send(&mut sgb, &packet);
+
+        // Inside only: the border follows the inside palette
+        let mut packet = [0u8; 16];
+        packet[0] = (ATTR_BLK << 3) | 1;
+        packet[1] = 1;
+        packet[2..8].copy_from_slice(&[0b001, 0b10, 2, 2, 4, 4]);
+        send(&mut sgb, &packet);
+
+        // Horizontal line 10 and vertical line 0
+        let mut packet = [0u8; 16];
+        packet[0] = (ATTR_LIN << 3) | 1;
+        packet[1] = 2;
+        packet[2] = 0x80 | (3 << 5) | 10;
+        packet[3] = 3 << 5;
+        send(&mut sgb, &packet);
+
+        let frame = sgb.compose(&vec![1u32; FB_LEN]);
+        let gray = screen_pixel(&frame, 8, 8);
+        assert_eq!(screen_pixel(&frame, 16, 16), 0xFFFF0000, "Block border");
+        assert_eq!(screen_pixel(&frame, 24, 24), 0xFFFF0000, "Block inside");
+        assert_eq!(screen_pixel(&frame, 40, 40), gray, "Block outside unchanged");
+        assert_eq!(screen_pixel(&frame, 80, 80), 0xFF0000FF, "Horizontal line");
+        assert_eq!(screen_pixel(&frame, 0, 0), 0xFF0000FF, "Vertical line");
+        assert_eq!(screen_pixel(&frame, GB_W - 1, 0), gray);
+    }
+
+    #[test]
+    fn test_vram_transfers() {
+        let mut sgb = Sgb::default();
+
+        // System palette 5 is green
+        let mut packet = [0u8; 16];
+        packet[0] = (PAL_TRN << 3) | 1;
+        send(&mut sgb, &packet);
+        assert!(sgb.transfer_pending());
+        let mut data = vec![0u8; TRANSFER_LEN];
+        data[5 * 8 + 2..5 * 8 + 4].copy_from_slice(&0x03E0u16.to_le_bytes());
+        sgb.finish_transfer(&data);
+        assert!(!sgb.transfer_pending());
+
+        let mut packet = [0u8; 16];
+        packet[0] = (PAL_SET << 3) | 1;
+        packet[1] = 5;
+        send(&mut sgb, &packet);
+        let frame = sgb.compose(&vec![1u32; FB_LEN]);
+        assert_eq!(screen_pixel(&frame, 0, 0), 0xFF00FF00, "PAL_SET");
+
+        // Border tile 1 uses color 1 of palette 4 on its first row
+        let mut packet = [0u8; 16];
+        packet[0] = (CHR_TRN << 3) | 1;
+        send(&mut sgb, &packet);
+        let mut data = vec![0u8; TRANSFER_LEN];
+        data[32] = 0xFF;
+        sgb.finish_transfer(&data);
+
+        let mut packet = [0u8; 16];
+        packet[0] = (PCT_TRN << 3) | 1;
+        send(&mut sgb, &packet);
+        let mut data = vec![0u8; TRANSFER_LEN];
+        data[0..2].copy_from_slice(&(0x4000u16 | 1).to_le_bytes());
+        data[0x802..0x804].copy_from_slice(&0x7C00u16.to_le_bytes());
+        sgb.finish_transfer(&data);
+
+        let frame = sgb.compose(&vec![1u32; FB_LEN]);
+        assert_eq!(frame[0], 0xFF0000FF, "Border tile");
+        assert_eq!(frame[SGB_W], 0xFF000000, "Transparent border pixel");
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        let mut w = StateWriter::default();
+        Sgb::default().save_state(&mut w);
+        let data = w.into_bytes();
+        // Receiving, bit count, packet, empty command and P14/P15 lines
+        let players = 1 + 1 + 4 + PACKET_LEN + 4 + 1;
+
+        let mut bits = data.clone();
+        bits[1] = 0xFF;
+        let mut player = data.clone();
+        player[players + 1] = 1;
+        let mut players_count = data.clone();
+        players_count[players] = 3;
+
+        let mut sgb = Sgb::default();
+        assert_eq!(sgb.load_state(&mut StateReader::new(&bits)), Err(invalid_tag("SGB packet bit count", 0xFF)));
+        assert_eq!(sgb.load_state(&mut StateReader::new(&player)), Err(invalid_tag("SGB player", 1)));
+        assert_eq!(sgb.load_state(&mut StateReader::new(&players_count)), Err(invalid_tag("SGB player count", 3)));
+        assert_eq!(sgb.load_state(&mut StateReader::new(&data)), Ok(()));
+    }
+}
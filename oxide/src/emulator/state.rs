@@ -0,0 +1,223 @@
+use super::*;
+use std::fs;
+
+/*
+ * Save states.
+ * A state starts with a magic number, the format version, the model and the
+ * checksums of the cartridge it was made with. Each component then writes its
+ * fields in a fixed order, integers in little endian. Buffers are prefixed
+ * with their length, which has to match the emulator on load.
+ * The ROMs and the settings are not part of the state: it can only be loaded
+ * by an emulator running the same cartridge on the same model.
+ */
+
+pub const STATE_MAGIC: &[u8; 4] = b"OXGB";
+
+// Increased whenever the layout of a component changes
+pub const STATE_VERSION: u16 = 2;
+
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String>;
+}
+
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.buf.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend(value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self.data.get(self.pos..self.pos + N).ok_or("Truncated save state")?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or("Truncated save state")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // Reads a buffer which has to be the size of dest
+    pub fn bytes_into(&mut self, dest: &mut [u8]) -> Result<(), String> {
+        let bytes = self.bytes()?;
+        if bytes.len() != dest.len() {
+            return Err(format!("Save state buffer of {} bytes, expected {}", bytes.len(), dest.len()));
+        }
+        dest.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+// Error for an enum tag out of range
+pub fn invalid_tag(what: &str, tag: u8) -> String {
+    format!("Invalid {what} in save state: {tag}")
+}
+
+fn model_tag(model: Model) -> u8 {
+    match model {
+        Model::Auto => 0,
+        Model::Dmg => 1,
+        Model::Cgb => 2,
+        Model::Sgb => 3,
+    }
+}
+
+impl Emulator {
+    // Snapshot of the whole emulated machine
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        let header = self.bus.cartridge.header();
+
+        w.buf.extend(STATE_MAGIC);
+        w.u16(STATE_VERSION);
+        w.u8(model_tag(self.bus.model));
+        w.u8(header.header_checksum);
+        w.u16(header.global_checksum);
+
+        w.u64(self.ticks as u64);
+        self.cpu.save_state(&mut w);
+        self.bus.save_state(&mut w);
+        self.ppu.save_state(&mut w);
+        self.timer.save_state(&mut w);
+        self.apu.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    // Restores a snapshot made by save_state. The emulator is left untouched
+    // if the state is invalid
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let backup = self.save_state();
+        let res = self.read_state(data);
+        if res.is_err() {
+            self.read_state(&backup).expect("Could not restore the emulator state");
+        }
+        res
+    }
+
+    pub fn save_state_file(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.save_state()).map_err(|e| format!("Could not write {}: {e}", path.display()))
+    }
+
+    pub fn load_state_file(&mut self, path: &Path) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        self.load_state(&data)
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut r = StateReader::new(data);
+        let header = self.bus.cartridge.header();
+
+        if r.take::<4>().ok().as_ref() != Some(STATE_MAGIC) {
+            return Err("Not a save state".to_string());
+        }
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(format!("Unsupported save state version {version}, expected {STATE_VERSION}"));
+        }
+        if r.u8()? != model_tag(self.bus.model) {
+            return Err(format!("The save state was not made on the {:?} model", self.bus.model));
+        }
+        if r.u8()? != header.header_checksum || r.u16()? != header.global_checksum {
+            return Err("The save state was made with another cartridge".to_string());
+        }
+
+        self.ticks = r.u64()? as usize;
+        self.cpu.load_state(&mut r)?;
+        self.bus.load_state(&mut r)?;
+        self.ppu.load_state(&mut r)?;
+        self.timer.load_state(&mut r)?;
+        self.apu.load_state(&mut r)?;
+
+        if !r.is_empty() {
+            return Err("Unexpected data at the end of the save state".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/state.rs"]
+mod state_tests;
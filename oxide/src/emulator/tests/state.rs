@@ -0,0 +1,142 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::DummyDebugger;
+    use crate::emulator::internals::iomanager::IoManager;
+    use crate::emulator::memory::cartridge::header::*;
+    use crate::emulator::{Emulator, Model};
+    use crate::settings::{Settings, GLOB_SETTINGS};
+    use crossbeam_channel::bounded;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, AtomicU8};
+    use std::sync::Arc;
+
+    // MBC3 program keeping the CPU, timer, cartridge RAM and WRAM busy
+    const PROGRAM: [u8; 34] = [
+        0x3E, 0x0A,       // ld a, 0x0A
+        0xEA, 0x00, 0x00, // ld (0x0000), a  ; Enable the cartridge RAM
+        0x3E, 0x05,       // ld a, 0x05
+        0xE0, 0x07,       // ldh (TAC), a    ; Timer at 262144Hz
+        0x3E, 0x04,       // ld a, 0x04
+        0xE0, 0xFF,       // ldh (IE), a     ; Timer interrupt
+        0xFB,             // ei
+        0x21, 0x00, 0xC0, // ld hl, 0xC000
+        0x04,             // loop: inc b
+        0x78,             // ld a, b
+        0x22,             // ld (hl+), a
+        0x7C,             // ld a, h
+        0xFE, 0xD0,       // cp 0xD0
+        0x20, 0x02,       // jr nz, +2
+        0x26, 0xC0,       // ld h, 0xC0
+        0xEA, 0x00, 0xA0, // ld (0xA000), a
+        0x0F,             // rrca
+        0x00,             // nop
+        0x18, 0xEF,       // jr loop
+    ];
+
+    fn make_rom(title: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x50..0x52].copy_from_slice(&[0x14, 0xD9]); // Timer handler: inc d, reti
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // nop, jp 0x0150
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom[0x147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+        rom[0x149] = 0x02;
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+        let global = CartridgeHeader::compute_global_checksum(&rom);
+        rom[0x14E..0x150].copy_from_slice(&global.to_be_bytes());
+        rom[0x150..0x150 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+        rom
+    }
+
+    // Removes the ROM and save files of a test when dropped
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn emulator(title: &str, model: Model) -> (Emulator, TempDir) {
+        GLOB_SETTINGS.get_or_init(|| Arc::new(Settings { sample_rate: 48000, ..Default::default() }));
+
+        let dir = std::env::temp_dir().join(format!("oxide_state_{}_{title}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("rom.gb");
+        std::fs::write(&rom_path, make_rom(title.as_bytes())).unwrap();
+
+        let (tx_frame, _) = bounded(1);
+        let io_manager = IoManager::new(tx_frame, Arc::new(AtomicU8::new(0)), Arc::new(AtomicBool::new(false)), false);
+        (Emulator::new(rom_path, PathBuf::new(), io_manager, model).unwrap(), TempDir(dir))
+    }
+
+    fn run(emu: &mut Emulator, ticks: usize) {
+        let mut dbg = DummyDebugger::default();
+        for _ in 0..ticks {
+            emu.tick(&mut dbg);
+        }
+    }
+
+    fn check_round_trip(mut emu: Emulator) {
+        // Stop in the middle of an instruction and of a scanline
+        run(&mut emu, 200_003);
+        let state = emu.save_state();
+
+        run(&mut emu, 100_000);
+        let expected = emu.save_state();
+        assert_ne!(state, expected, "The emulator state changed");
+
+        emu.load_state(&state).unwrap();
+        assert_eq!(emu.save_state(), state, "Loaded state");
+        run(&mut emu, 100_000);
+        assert_eq!(emu.save_state(), expected, "Same state after running from the loaded one");
+        assert_ne!(emu.cpu.d, 0, "Timer interrupts were handled");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (emu, _dir) = emulator("ROUNDTRIP", Model::Dmg);
+        check_round_trip(emu);
+    }
+
+    // With VRAM and WRAM banks, and the DMG compatibility palettes
+    #[test]
+    fn test_round_trip_cgb() {
+        let (emu, _dir) = emulator("ROUNDTRIPCGB", Model::Cgb);
+        check_round_trip(emu);
+    }
+
+    #[test]
+    fn test_load_in_new_emulator() {
+        let (mut emu, _dir) = emulator("NEWEMU", Model::Dmg);
+        run(&mut emu, 150_001);
+        let state = emu.save_state();
+        run(&mut emu, 50_000);
+
+        let (mut other, _other_dir) = emulator("NEWEMU", Model::Dmg);
+        other.load_state(&state).unwrap();
+        run(&mut other, 50_000);
+        assert_eq!(other.save_state(), emu.save_state());
+        assert_eq!(other.bus.read(0xA000), emu.bus.read(0xA000), "Cartridge RAM");
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        let (mut emu, _dir) = emulator("INVALID", Model::Dmg);
+        run(&mut emu, 10_000);
+        let state = emu.save_state();
+        let before = state.clone();
+
+        let mut version = state.clone();
+        version[4] = 0xFF;
+        assert!(emu.load_state(&version).is_err(), "Unsupported version");
+        assert!(emu.load_state(&state[..state.len() - 1]).is_err(), "Truncated state");
+        assert!(emu.load_state(b"nope").is_err(), "Not a state");
+        let other = emulator("OTHERGAME", Model::Dmg).0.save_state();
+        assert!(emu.load_state(&other).is_err(), "Other cartridge");
+
+        // The truncated state was partially read before failing
+        assert_eq!(emu.save_state(), before, "Emulator restored after a failed load");
+    }
+}
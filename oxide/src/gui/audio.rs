@@ -0,0 +1,166 @@
+use crossbeam_channel::Receiver;
+use log::info;
+use sdl3::Sdl;
+use sdl3::audio::{AudioCallback, AudioFormat, AudioSpec, AudioStream, AudioStreamWithCallback};
+use crate::emulator::apu::AudioChunk;
+
+/*
+ * Audio output.
+ * The emulator is paced by the video frame rate, which never exactly matches
+ * the audio device clock. Samples received from the worker are stored in a
+ * ring buffer, and played back with a ratio depending on its fill level:
+ * slightly faster when it grows over the target latency, slightly slower when
+ * it drains. The pitch change is inaudible, and there are no more
+ * crackles from underruns or growing latency.
+ */
+
+// Buffered audio the resampler tries to keep, in stereo frames (~64ms at 48kHz)
+const TARGET_FRAMES: usize = 3072;
+
+// Maximum playback speed deviation
+const MAX_RATIO_DELTA: f64 = 0.005;
+
+// Fade applied to the last played frame on underruns, to avoid clicks
+const UNDERRUN_FADE: f32 = 0.995;
+
+pub struct RingBuffer {
+    data: Vec<[f32; 2]>,
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer { data: vec![[0.0; 2]; capacity], head: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Returns false when the buffer is full and the frame was dropped
+    pub fn push(&mut self, frame: [f32; 2]) -> bool {
+        if self.len == self.data.len() {
+            return false;
+        }
+        let tail = (self.head + self.len) % self.data.len();
+        self.data[tail] = frame;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<[f32; 2]> {
+        if self.len == 0 {
+            return None;
+        }
+        let frame = self.data[self.head];
+        self.head = (self.head + 1) % self.data.len();
+        self.len -= 1;
+        Some(frame)
+    }
+
+    // Frame at the given offset from the oldest one
+    pub fn get(&self, offset: usize) -> Option<[f32; 2]> {
+        if offset >= self.len {
+            return None;
+        }
+        Some(self.data[(self.head + offset) % self.data.len()])
+    }
+}
+
+pub struct Resampler {
+    ring: RingBuffer,
+    position: f64,  // Fractional position between the two oldest frames
+    playing: bool,  // Waits for the target latency after an underrun
+    last: [f32; 2],
+}
+
+impl Resampler {
+    pub fn new() -> Self {
+        Resampler {
+            ring: RingBuffer::new(TARGET_FRAMES * 4),
+            position: 0.0,
+            playing: false,
+            last: [0.0; 2],
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[f32]) {
+        for frame in chunk.chunks_exact(2) {
+            if !self.ring.push([frame[0], frame[1]]) {
+                break;
+            }
+        }
+    }
+
+    // Input frames consumed for each output frame
+    pub fn ratio(&self) -> f64 {
+        let error = (self.ring.len() as f64 - TARGET_FRAMES as f64) / TARGET_FRAMES as f64;
+        1.0 + (error * MAX_RATIO_DELTA).clamp(-MAX_RATIO_DELTA, MAX_RATIO_DELTA)
+    }
+
+    // Fills an interleaved stereo buffer
+    pub fn fill(&mut self, out: &mut [f32]) {
+        if !self.playing && self.ring.len() >= TARGET_FRAMES {
+            self.playing = true;
+        }
+
+        let ratio = self.ratio();
+        for frame in out.chunks_exact_mut(2) {
+            let (Some(a), Some(b)) = (self.ring.get(0), self.ring.get(1)) else {
+                self.playing = false;
+                self.last = self.last.map(|v| v * UNDERRUN_FADE);
+                frame.copy_from_slice(&self.last);
+                continue;
+            };
+            if !self.playing {
+                frame.copy_from_slice(&self.last);
+                continue;
+            }
+
+            let t = self.position.min(1.0) as f32;
+            self.last = [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+            frame.copy_from_slice(&self.last);
+
+            self.position += ratio;
+            while self.position >= 1.0 && self.ring.len() >= 2 {
+                self.ring.pop();
+                self.position -= 1.0;
+            }
+        }
+    }
+}
+
+// Runs on the SDL audio thread
+pub struct AudioFeeder {
+    rx_audio: Receiver<AudioChunk>,
+    resampler: Resampler,
+}
+
+impl AudioCallback<f32> for AudioFeeder {
+    fn callback(&mut self, stream: &mut AudioStream, requested: i32) {
+        while let Ok(chunk) = self.rx_audio.try_recv() {
+            self.resampler.push(&chunk);
+        }
+
+        let mut out = vec![0.0; requested as usize & !1];
+        self.resampler.fill(&mut out);
+        let _ = stream.put_data_f32(&out);
+    }
+}
+
+// The returned stream plays until dropped
+pub fn start_audio(sdl: &Sdl, rx_audio: Receiver<AudioChunk>, sample_rate: u32) -> Result<AudioStreamWithCallback<AudioFeeder>, Box<dyn std::error::Error>> {
+    let audio = sdl.audio()?;
+    let spec = AudioSpec::new(Some(sample_rate as i32), Some(2), Some(AudioFormat::f32_sys()));
+    let feeder = AudioFeeder { rx_audio, resampler: Resampler::new() };
+
+    let stream = audio.open_playback_stream(&spec, feeder)?;
+    stream.resume()?;
+    info!("Audio output started on {}", audio.current_audio_driver());
+    Ok(stream)
+}
+
+#[cfg(test)]
+#[path = "tests/audio.rs"]
+mod audio_tests;
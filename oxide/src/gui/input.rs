@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use crate::emulator::internals::iomanager::StateRequest;
+use log::{info, warn};
+use sdl3::event::Event;
+use sdl3::GamepadSubsystem;
+use sdl3::gamepad::{Axis, Button, Gamepad};
+use sdl3::keyboard::Keycode;
+
+/*
+ * Keyboard and gamepad handling.
+ * Pressed buttons are set to 1 in the shared joypad state, using the bit
+ * layout documented in IoManager. The keyboard, the gamepad buttons and the
+ * analog stick are tracked separately, a button is held while any of them
+ * holds it.
+ * F5 and F8 ask the emulator thread to save and load a state.
+ */
+
+// Analog stick position past which it acts as a D-Pad
+const AXIS_THRESHOLD: i16 = 16384;
+
+// Rumble effects stop after this duration if not updated
+const RUMBLE_DURATION_MS: u32 = 60_000;
+
+const SAVE_STATE_KEY: Keycode = Keycode::F5;
+const LOAD_STATE_KEY: Keycode = Keycode::F8;
+
+// Shared with the emulator thread
+pub struct InputHandles {
+    pub joystate: Arc<AtomicU8>,
+    pub rumble: Arc<AtomicBool>,
+    pub state_request: Arc<AtomicU8>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum JoypadButton {
+    A = 0,
+    B,
+    Select,
+    Start,
+    Right,
+    Left,
+    Up,
+    Down,
+}
+
+impl JoypadButton {
+    pub const ALL: [JoypadButton; 8] = [
+        JoypadButton::A, JoypadButton::B, JoypadButton::Select, JoypadButton::Start,
+        JoypadButton::Right, JoypadButton::Left, JoypadButton::Up, JoypadButton::Down,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        JoypadButton::ALL.into_iter().find(|b| b.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JoypadButton::A => "a",
+            JoypadButton::B => "b",
+            JoypadButton::Select => "select",
+            JoypadButton::Start => "start",
+            JoypadButton::Right => "right",
+            JoypadButton::Left => "left",
+            JoypadButton::Up => "up",
+            JoypadButton::Down => "down",
+        }
+    }
+
+    fn mask(&self) -> u8 {
+        1 << (*self as u8)
+    }
+
+    fn from_gamepad(button: Button) -> Option<Self> {
+        match button {
+            Button::South => Some(JoypadButton::A),
+            Button::East => Some(JoypadButton::B),
+            Button::Back => Some(JoypadButton::Select),
+            Button::Start => Some(JoypadButton::Start),
+            Button::DPadRight => Some(JoypadButton::Right),
+            Button::DPadLeft => Some(JoypadButton::Left),
+            Button::DPadUp => Some(JoypadButton::Up),
+            Button::DPadDown => Some(JoypadButton::Down),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyBindings {
+    keys: HashMap<Keycode, JoypadButton>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            keys: HashMap::from([
+                (Keycode::X, JoypadButton::A),
+                (Keycode::Z, JoypadButton::B),
+                (Keycode::Backspace, JoypadButton::Select),
+                (Keycode::Return, JoypadButton::Start),
+                (Keycode::Right, JoypadButton::Right),
+                (Keycode::Left, JoypadButton::Left),
+                (Keycode::Up, JoypadButton::Up),
+                (Keycode::Down, JoypadButton::Down),
+            ])
+        }
+    }
+}
+
+impl KeyBindings {
+    // Parses a list of bindings overriding the default ones
+    // Format: button=Key,button=Key... e.g. "a=K,b=J,start=Space"
+    // A key cannot be bound to two buttons, buttons swapping keys have to be
+    // rebound together
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut overrides = HashMap::new();
+
+        for binding in spec.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let (button, key) = binding.split_once('=')
+                .ok_or(format!("Invalid key binding: {binding}. Expected button=Key"))?;
+            let button = JoypadButton::from_name(button.trim())
+                .ok_or(format!("Unknown joypad button: {button}"))?;
+            let key = Keycode::from_name(key.trim())
+                .ok_or(format!("Unknown key name: {key}"))?;
+            if key == SAVE_STATE_KEY || key == LOAD_STATE_KEY {
+                return Err(format!("Key {} is reserved for save states", key.name()));
+            }
+            overrides.insert(button, key);
+        }
+
+        let mut bindings = Self::default();
+        bindings.keys.retain(|_, b| !overrides.contains_key(b));
+        for (button, key) in overrides {
+            if let Some(other) = bindings.keys.insert(key, button) {
+                return Err(format!("Key {} is bound to both {} and {}", key.name(), other.name(), button.name()));
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn get(&self, key: Keycode) -> Option<JoypadButton> {
+        self.keys.get(&key).copied()
+    }
+}
+
+pub struct InputManager {
+    joystate: Arc<AtomicU8>,
+    rumble: Arc<AtomicBool>,
+    state_request: Arc<AtomicU8>,
+    keys: u8,   // Buttons held on the keyboard
+    pad: u8,    // Buttons held on the gamepads
+    stick: u8,  // Directions held with the analog stick
+    rumbling: bool,
+    bindings: KeyBindings,
+    gamepad_sys: GamepadSubsystem,
+    gamepads: Vec<Gamepad>,
+}
+
+impl InputManager {
+    pub fn new(handles: InputHandles, bindings: KeyBindings, gamepad_sys: GamepadSubsystem) -> Self {
+        InputManager {
+            joystate: handles.joystate,
+            rumble: handles.rumble,
+            state_request: handles.state_request,
+            keys: 0,
+            pad: 0,
+            stick: 0,
+            rumbling: false,
+            bindings,
+            gamepad_sys,
+            gamepads: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::KeyDown { keycode: Some(SAVE_STATE_KEY), repeat: false, .. } => self.request_state(StateRequest::Save),
+            Event::KeyDown { keycode: Some(LOAD_STATE_KEY), repeat: false, .. } => self.request_state(StateRequest::Load),
+            Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
+                if let Some(button) = self.bindings.get(*key) {
+                    set_button(&mut self.keys, button, true);
+                    self.update_joystate();
+                }
+            },
+            Event::KeyUp { keycode: Some(key), .. } => {
+                if let Some(button) = self.bindings.get(*key) {
+                    set_button(&mut self.keys, button, false);
+                    self.update_joystate();
+                }
+            },
+            Event::ControllerButtonDown { button, .. } => {
+                if let Some(button) = JoypadButton::from_gamepad(*button) {
+                    set_button(&mut self.pad, button, true);
+                    self.update_joystate();
+                }
+            },
+            Event::ControllerButtonUp { button, .. } => {
+                if let Some(button) = JoypadButton::from_gamepad(*button) {
+                    set_button(&mut self.pad, button, false);
+                    self.update_joystate();
+                }
+            },
+            Event::ControllerAxisMotion { axis, value, .. } => self.handle_axis(*axis, *value),
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.gamepad_sys.open(*which) {
+                    Ok(gamepad) => {
+                        info!("Gamepad connected: {}", gamepad.name().unwrap_or_default());
+                        self.gamepads.push(gamepad);
+                    },
+                    Err(e) => warn!("Could not open gamepad {which}: {e}"),
+                }
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.gamepads.retain(|g| g.id().is_ok_and(|id| id != *which));
+            },
+            _ => ()
+        }
+    }
+
+    // Forwards the cartridge rumble motor state to the gamepads
+    pub fn update_rumble(&mut self) {
+        let on = self.rumble.load(Ordering::Relaxed);
+        if on == self.rumbling {
+            return;
+        }
+        self.rumbling = on;
+
+        let strength = if on {0xFFFF} else {0};
+        for gamepad in &mut self.gamepads {
+            // Not all gamepads have a motor
+            let _ = gamepad.set_rumble(strength, strength, RUMBLE_DURATION_MS);
+        }
+    }
+
+    fn handle_axis(&mut self, axis: Axis, value: i16) {
+        let (negative, positive) = match axis {
+            Axis::LeftX => (JoypadButton::Left, JoypadButton::Right),
+            Axis::LeftY => (JoypadButton::Up, JoypadButton::Down),
+            _ => return,
+        };
+        set_button(&mut self.stick, negative, value < -AXIS_THRESHOLD);
+        set_button(&mut self.stick, positive, value > AXIS_THRESHOLD);
+        self.update_joystate();
+    }
+
+    fn request_state(&self, request: StateRequest) {
+        self.state_request.store(request as u8, Ordering::Relaxed);
+    }
+
+    fn update_joystate(&self) {
+        self.joystate.store(self.keys | self.pad | self.stick, Ordering::Relaxed);
+    }
+}
+
+fn set_button(state: &mut u8, button: JoypadButton, pressed: bool) {
+    if pressed {
+        *state |= button.mask();
+    } else {
+        *state &= !button.mask();
+    }
+}
@@ -1,11 +1,18 @@
-use std::sync::Arc;
-use std::sync::atomic::AtomicU8;
-use crossbeam_channel::Receiver;
+pub mod audio;
+pub mod input;
+
+use std::time::Duration;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
 use sdl3::{Sdl, VideoSubsystem};
-use sdl3::render::{Canvas, TextureCreator, WindowCanvas, Texture};
-use sdl3::video::{Window, WindowContext};
-use crate::emulator::ppu::Frame;
-use image::GenericImageView;
+use sdl3::event::{Event, WindowEvent};
+use sdl3::render::{FRect, ScaleMode, TextureCreator, WindowCanvas, Texture};
+use sdl3::video::WindowContext;
+use crate::emulator::apu::AudioChunk;
+use crate::emulator::ppu::{frame_size, Frame};
+use crate::emulator::sgb::{SGB_H, SGB_W};
+use crate::settings::GLOB_SETTINGS;
+use log::warn;
+use input::{InputHandles, InputManager, KeyBindings};
 use sdl3::pixels::PixelFormatEnum;
 
 const BG_BYTES : &[u8] = include_bytes!("../../assets/dmg_background.png");
@@ -15,53 +22,92 @@ const SCR_W : u32 = 160;
 const SCR_H : u32 = 144;
 const SCR_X : i32 = 75;
 const SCR_Y : i32 = 70;
-pub struct Sdl_Ui {
+
+// How long to wait for a frame before handling window events again
+const FRAME_TIMEOUT: Duration = Duration::from_millis(16);
+
+pub struct SdlUi {
     pub sdl: Sdl,
     pub video: VideoSubsystem,
     pub canvas: WindowCanvas,
     pub tex_creator: TextureCreator<WindowContext>,
 }
 
-impl Sdl_Ui {
-    pub fn new() -> Result<Sdl_Ui, Box<dyn std::error::Error>> {
+// Window size and screen position, in unscaled pixels.
+// SGB frames are drawn with their border instead of the DMG bezel
+#[derive(Copy, Clone, PartialEq)]
+struct Layout {
+    win_w: u32,
+    win_h: u32,
+    screen: FRect,
+    bezel: bool,
+}
+
+impl Layout {
+    fn for_frame(frame: &Frame) -> Self {
+        match frame_size(frame) {
+            (SGB_W, SGB_H) => Layout {
+                win_w: SGB_W as u32, win_h: SGB_H as u32,
+                screen: FRect::new(0.0, 0.0, SGB_W as f32, SGB_H as f32),
+                bezel: false,
+            },
+            _ => Layout::default(),
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            win_w: BG_W, win_h: BG_H,
+            screen: FRect::new(SCR_X as f32, SCR_Y as f32, SCR_W as f32, SCR_H as f32),
+            bezel: true,
+        }
+    }
+}
+
+impl SdlUi {
+    pub fn new(scale: u32, title: &str) -> Result<SdlUi, Box<dyn std::error::Error>> {
         let sdl = sdl3::init()?;
         let video = sdl.video()?;
-        let window = video.window("OxideGB", 311, 276)
+        let window = video.window(title, BG_W * scale, BG_H * scale)
             .position_centered()
             .build()?;
-        let canvas = window.into_canvas();
+        let mut canvas = window.into_canvas();
+        canvas.set_scale(scale as f32, scale as f32)?;
         let tex_creator = canvas.texture_creator();
 
-        Ok(Sdl_Ui {
+        Ok(SdlUi {
             sdl, video, canvas, tex_creator
         })
     }
+}
 
-    fn get_bg_texture(&mut self) -> Result<Texture, Box<dyn std::error::Error>> {
-        let bg_image = image::load_from_memory(BG_BYTES)?.to_rgba8();
-        let mut bg_tex = self.tex_creator.create_texture_streaming(
-            Some(PixelFormatEnum::ABGR8888.into()), BG_W, BG_H)?;
-        bg_tex.set_blend_mode(sdl3::render::BlendMode::Blend);
+fn get_bg_texture(tex_creator: &TextureCreator<WindowContext>) -> Result<Texture<'_>, Box<dyn std::error::Error>> {
+    let bg_image = image::load_from_memory(BG_BYTES)?.to_rgba8();
+    let mut bg_tex = tex_creator.create_texture_streaming(
+        Some(PixelFormatEnum::ABGR8888.into()), BG_W, BG_H)?;
+    bg_tex.set_blend_mode(sdl3::render::BlendMode::Blend);
+    bg_tex.set_scale_mode(ScaleMode::Nearest);
 
-        bg_tex.with_lock(None, |buf, pitch | {
-            let src = bg_image.as_raw();
+    bg_tex.with_lock(None, |buf, pitch | {
+        let src = bg_image.as_raw();
 
-            for y in 0..BG_H as usize {
-                let src_row = &src[y * (BG_W as usize) * 4 .. (y + 1) * (BG_W as usize) * 4];
-                let dst_row = &mut buf[y * pitch as usize .. y * pitch as usize + (BG_W as usize) * 4];
-                dst_row.copy_from_slice(src_row);
-            }
-        })?;
-        Ok(bg_tex)
-    }
-    
+        for y in 0..BG_H as usize {
+            let src_row = &src[y * (BG_W as usize) * 4 .. (y + 1) * (BG_W as usize) * 4];
+            let dst_row = &mut buf[y * pitch .. y * pitch + (BG_W as usize) * 4];
+            dst_row.copy_from_slice(src_row);
+        }
+    })?;
+    Ok(bg_tex)
 }
 
 fn write_frame(tex: &mut Texture, frame: &Frame) {
+    let (w, h) = frame_size(frame);
     tex.with_lock(None, |buf, pitch| {
-        for y in 0..SCR_H as usize {
-            let row = &frame[y * SCR_W as usize .. (y + 1) * SCR_W as usize];
-            let dst = &mut buf[y * pitch as usize .. y * pitch as usize + (SCR_W as usize) * 4];
+        for y in 0..h {
+            let row = &frame[y * w .. (y + 1) * w];
+            let dst = &mut buf[y * pitch .. y * pitch + w * 4];
 
             for (x, &px) in row.iter().enumerate() {
                 let r = ((px >> 16) & 0xFF) as u8;
@@ -69,7 +115,7 @@ fn write_frame(tex: &mut Texture, frame: &Frame) {
                 let b = ( px        & 0xFF) as u8;
                 let i = x * 4;
                 // ARGB8888 (little-endian) expects BGRA bytes here:
-                dst[i + 0] = b;
+                dst[i] = b;
                 dst[i + 1] = g;
                 dst[i + 2] = r;
                 dst[i + 3] = 0xFF;
@@ -78,10 +124,58 @@ fn write_frame(tex: &mut Texture, frame: &Frame) {
     }).unwrap();
 }
 
-pub fn start_gui(rx_frame: Receiver<Frame>, joystate: Arc<AtomicU8>) {
-    let mut ui = Sdl_Ui::new().unwrap();
-    let scren_text = ui.tex_creator.create_texture_streaming(
-        Some(PixelFormatEnum::ARGB8888.into()), SCR_W, SCR_H);
-    let bg_text = ui.get_bg_texture().unwrap();
-        
-}
\ No newline at end of file
+fn screen_texture<'a>(tex_creator: &'a TextureCreator<WindowContext>, layout: &Layout) -> Result<Texture<'a>, Box<dyn std::error::Error>> {
+    let mut tex = tex_creator.create_texture_streaming(
+        Some(PixelFormatEnum::ARGB8888.into()), layout.screen.w as u32, layout.screen.h as u32)?;
+    tex.set_scale_mode(ScaleMode::Nearest);
+    Ok(tex)
+}
+
+// Runs the frontend until the window is closed or the emulator stops
+pub fn start_gui(rx_frame: Receiver<Frame>, rx_audio: Receiver<AudioChunk>, handles: InputHandles, scale: u32, bindings: KeyBindings, title: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut layout = Layout::default();
+    let mut ui = SdlUi::new(scale, title)?;
+    let mut events = ui.sdl.event_pump()?;
+    let mut input = InputManager::new(handles, bindings, ui.sdl.gamepad()?);
+    // Keep running without sound if there is no audio device
+    let _audio = audio::start_audio(&ui.sdl, rx_audio, GLOB_SETTINGS.get().unwrap().sample_rate)
+        .inspect_err(|e| warn!("Could not start audio output: {e}"));
+    let mut screen_tex = screen_texture(&ui.tex_creator, &layout)?;
+    let bg_tex = get_bg_texture(&ui.tex_creator)?;
+
+    let bg_rect = FRect::new(0.0, 0.0, BG_W as f32, BG_H as f32);
+
+    'running: loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } |
+                Event::Window { win_event: WindowEvent::CloseRequested, .. } => break 'running,
+                _ => input.handle_event(&event)
+            }
+        }
+        input.update_rumble();
+
+        match rx_frame.recv_timeout(FRAME_TIMEOUT) {
+            Ok(frame) => {
+                // The window is resized on the first SGB frame
+                let new_layout = Layout::for_frame(&frame);
+                if new_layout != layout {
+                    layout = new_layout;
+                    ui.canvas.window_mut().set_size(layout.win_w * scale, layout.win_h * scale)?;
+                    screen_tex = screen_texture(&ui.tex_creator, &layout)?;
+                }
+                write_frame(&mut screen_tex, &frame);
+            },
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break 'running,
+        }
+
+        ui.canvas.clear();
+        if layout.bezel {
+            ui.canvas.copy(&bg_tex, None, bg_rect)?;
+        }
+        ui.canvas.copy(&screen_tex, None, layout.screen)?;
+        ui.canvas.present();
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+
+#[cfg(test)]
+mod tests {
+    use crate::gui::audio::*;
+
+    fn chunk(frames: usize, value: f32) -> Vec<f32> {
+        vec![value; frames * 2]
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut ring = RingBuffer::new(3);
+        assert!(ring.push([1.0, 1.0]));
+        assert!(ring.push([2.0, 2.0]));
+        assert!(ring.push([3.0, 3.0]));
+        assert!(!ring.push([4.0, 4.0]), "Full buffer drops frames");
+
+        assert_eq!(ring.pop(), Some([1.0, 1.0]));
+        assert!(ring.push([4.0, 4.0]), "Wraps around");
+        assert_eq!(ring.get(2), Some([4.0, 4.0]), "Offset from the oldest frame");
+        assert_eq!(ring.get(3), None);
+        assert_eq!(ring.len(), 3);
+    }
+
+    #[test]
+    fn test_waits_for_target_latency() {
+        let mut resampler = Resampler::new();
+        resampler.push(&chunk(100, 0.5));
+
+        let mut out = vec![1.0; 64];
+        resampler.fill(&mut out);
+        assert!(out.iter().all(|v| *v == 0.0), "Silence before the buffer is filled");
+
+        resampler.push(&chunk(4000, 0.5));
+        resampler.fill(&mut out);
+        assert!(out.iter().all(|v| *v == 0.5), "Playing");
+    }
+
+    #[test]
+    fn test_rate_control() {
+        let mut resampler = Resampler::new();
+        resampler.push(&chunk(3072, 0.0));
+        assert_eq!(resampler.ratio(), 1.0, "Target latency");
+
+        resampler.push(&chunk(6144, 0.0));
+        assert!(resampler.ratio() > 1.0 && resampler.ratio() <= 1.005, "Plays faster when late");
+
+        let mut resampler = Resampler::new();
+        resampler.push(&chunk(1000, 0.0));
+        assert!(resampler.ratio() < 1.0 && resampler.ratio() >= 0.995, "Plays slower when early");
+    }
+
+    #[test]
+    fn test_underrun_fades_out() {
+        let mut resampler = Resampler::new();
+        resampler.push(&chunk(3072, 0.5));
+
+        let mut out = vec![0.0; 3072 * 2 + 200];
+        resampler.fill(&mut out);
+        let tail = &out[out.len() - 2..];
+        assert!(tail[0] < 0.5 && tail[0] > 0.0, "Last frame fades out");
+    }
+}
@@ -0,0 +1,124 @@
+use crate::debugger::{DebugEvent, Debugger};
+use crate::emulator::cpu::Cpu;
+use crate::emulator::internals::iomanager::IoManager;
+use crate::emulator::memory::Bus;
+use crate::emulator::ppu::{frame_size, Frame, Ppu, FB_LEN};
+use crate::emulator::{Emulator, Model};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8};
+use std::sync::Arc;
+
+/*
+ * Headless run mode.
+ * Runs the emulator without SDL as fast as possible, for a number of frames
+ * or until a stop condition is met, and dumps frames as PNG files.
+ */
+
+// T-cycles per frame. Frames are counted in emulated time so that a ROM
+// keeping the LCD off still progresses.
+const FRAME_TICKS: usize = 70224;
+
+// LD B,B: software breakpoint used by test ROMs (e.g. mooneye) to signal the end
+const LD_B_B: u8 = 0x40;
+
+#[derive(Debug, Default)]
+pub struct HeadlessConfig {
+    pub frames: usize,           // Maximum number of frames to run
+    pub dump_frames: Vec<usize>, // Frames to write. The last one if empty
+    pub output: PathBuf,         // Directory of the PNG files
+    pub until_ld_b_b: bool,      // Stop when LD B,B is executed
+    pub until_serial: Vec<String>, // Stop when the serial output contains one of these
+    pub model: Model,
+}
+
+#[derive(Debug, Default)]
+struct HeadlessDebugger {
+    ld_b_b: bool,
+}
+
+impl Debugger for HeadlessDebugger {
+    fn on_cpu_event(&mut self, event: DebugEvent, _cpu: &Cpu, _bus: &Bus) {
+        // Only emitted for unprefixed opcodes, so CB 40 (BIT 0,B) does not match
+        if let DebugEvent::IrPrefetch(LD_B_B, _) = event {
+            self.ld_b_b = true;
+        }
+    }
+
+    fn on_ppu_event(&mut self, _event: DebugEvent, _ppu: &Ppu, _bus: &Bus) {}
+}
+
+impl HeadlessConfig {
+    fn has_condition(&self) -> bool {
+        self.until_ld_b_b || !self.until_serial.is_empty()
+    }
+
+    fn condition_met(&self, emu: &Emulator, dbg: &HeadlessDebugger) -> bool {
+        if self.until_ld_b_b && dbg.ld_b_b {
+            return true;
+        }
+
+        match &emu.bus.io_manager.serial_log {
+            Some(log) => {
+                let text = String::from_utf8_lossy(log);
+                self.until_serial.iter().any(|s| text.contains(s.as_str()))
+            },
+            None => false
+        }
+    }
+}
+
+// Runs the ROM and returns whether the stop condition was met.
+// Without stop condition, reaching the frame count is a success.
+pub fn run_headless(rom_path: String, boot_path: String, config: &HeadlessConfig) -> Result<bool, String> {
+    let (tx_frame, rx_frame) : (Sender<Frame>, Receiver<Frame>) = bounded(2);
+    let mut io_manager = IoManager::new(tx_frame, Arc::new(AtomicU8::new(0)), Arc::new(AtomicBool::new(false)), false);
+    io_manager.serial_log = Some(Vec::new());
+
+    let mut emu = Emulator::new(rom_path, boot_path, io_manager, config.model)?;
+    let mut dbg = HeadlessDebugger::default();
+    let mut frame: Frame = vec![0u32; FB_LEN].into_boxed_slice();
+    let mut met = false;
+    let mut count = 0;
+
+    std::fs::create_dir_all(&config.output)
+        .map_err(|e| format!("Could not create {}: {e}", config.output.display()))?;
+
+    while count < config.frames && !met {
+        for _ in 0..FRAME_TICKS {
+            emu.tick(&mut dbg);
+            // Keep only the last completed frame
+            if let Ok(f) = rx_frame.try_recv() {
+                frame = f;
+            }
+        }
+        count += 1;
+        met = config.condition_met(&emu, &dbg);
+
+        if config.dump_frames.contains(&count) {
+            write_png(&frame, &config.output.join(format!("frame_{count:05}.png")))?;
+        }
+    }
+
+    emu.save();
+    if config.dump_frames.is_empty() {
+        write_png(&frame, &config.output.join(format!("frame_{count:05}.png")))?;
+    }
+
+    if config.has_condition() {
+        println!("{} after {count} frames", if met {"Stop condition met"} else {"Stop condition not met"});
+        Ok(met)
+    } else {
+        Ok(true)
+    }
+}
+
+fn write_png(frame: &Frame, path: &Path) -> Result<(), String> {
+    let (w, h) = frame_size(frame);
+    let img = RgbaImage::from_fn(w as u32, h as u32, |x, y| {
+        let px = frame[y as usize * w + x as usize];
+        Rgba([(px >> 16) as u8, (px >> 8) as u8, px as u8, 0xFF])
+    });
+    img.save(path).map_err(|e| format!("Could not write {}: {e}", path.display()))
+}
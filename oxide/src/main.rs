@@ -2,20 +2,26 @@ pub mod emulator;
 pub mod debugger;
 mod settings;
 mod gui;
+mod headless;
 
 use crate::debugger::tui::ui_logger::UiLogger;
 use crate::debugger::*;
 use crate::emulator::*;
 
 use self::settings::*;
-use crate::emulator::internals::iomanager::IoManager;
-use crate::emulator::ppu::Frame;
+use crate::emulator::apu::AudioChunk;
+use crate::emulator::internals::iomanager::{IoManager, StateRequest};
+use crate::emulator::memory::cartridge::header::CartridgeHeader;
+use crate::emulator::ppu::{Frame, PpuBackend};
+use crate::gui::input::{InputHandles, KeyBindings};
+use crate::headless::{run_headless, HeadlessConfig};
 use clap::{Parser, ValueEnum};
 use crossbeam_channel::{bounded, Sender, Receiver};
 use debugger::tui::tui_main;
 use debugger::DummyDebugger;
 use std::fmt;
-use std::sync::atomic::AtomicU8;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
 
 #[macro_export]
@@ -55,6 +61,67 @@ struct Cli {
     #[arg(long = "doctor")]
     doctor_log: bool,
 
+    /// If enabled, then LY always reads 0x90, as expected by GameBoy Doctor
+    #[arg(long = "ly-stub")]
+    ly_stub: bool,
+
+    /// Integer scaling factor of the window
+    #[arg(short, long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..=8))]
+    scale: u32,
+
+    /// Key bindings overriding the defaults, e.g. "a=K,b=J,start=Space".
+    /// F5 and F8 are reserved to save and load states
+    #[arg(short, long)]
+    keys: Option<String>,
+
+    /// PPU rendering backend
+    #[arg(long, value_enum, default_value_t)]
+    ppu: PpuBackend,
+
+    /// Hardware model to emulate
+    #[arg(short, long, value_enum, default_value_t)]
+    model: Model,
+
+    /// Mimic the colors of the CGB LCD instead of displaying them at full saturation
+    #[arg(long)]
+    color_correction: bool,
+
+    /// Audio sample rate in Hz
+    #[arg(long, default_value_t = 48000, value_parser = clap::value_parser!(u32).range(8000..=192000))]
+    sample_rate: u32,
+
+    /// Record the audio output to a WAV file
+    #[arg(long, value_name = "WAV")]
+    record_audio: Option<PathBuf>,
+
+    /// With --record-audio, also record each channel to <name>_chN.wav
+    #[arg(long, requires = "record_audio")]
+    record_channels: bool,
+
+    /// Run without window, as fast as possible, and dump frames as PNG
+    #[arg(long)]
+    headless: bool,
+
+    /// Headless: maximum number of frames to run
+    #[arg(long, default_value_t = 600)]
+    frames: usize,
+
+    /// Headless: comma separated list of frames to dump. The last frame is dumped if empty
+    #[arg(long, value_delimiter = ',')]
+    dump_frames: Vec<usize>,
+
+    /// Headless: directory where frames are written
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+
+    /// Headless: stop when LD B,B is executed
+    #[arg(long)]
+    until_ld_b_b: bool,
+
+    /// Headless: stop when the serial output contains this text. Can be repeated
+    #[arg(long)]
+    until_serial: Vec<String>,
+
     /// Path of the GB ROM to load
     rom_path: String,
 }
@@ -85,22 +152,51 @@ impl fmt::Display for DebugMode {
 
 fn set_settings(cli: &Cli) {
     let tui_enabled = match cli.debug {
-        DebugMode::Full => true,
+        DebugMode::Full => !cli.headless,
         _ => false,
     };
     
     GLOB_SETTINGS.set(Arc::new(Settings {
         print_serial: cli.serial_print,
         tui_enabled,
-        doctor_logs: cli.doctor_log
+        doctor_logs: cli.doctor_log,
+        ly_stub: cli.ly_stub,
+        ppu_backend: cli.ppu,
+        sample_rate: cli.sample_rate,
+        record_audio: cli.record_audio.clone(),
+        record_channels: cli.record_channels,
+        color_correction: cli.color_correction,
     })).expect("Settings already initialized !");
 }
 
-fn launch_worker(cli: Cli, tx_frame: Sender<Frame>, joystate: Arc<AtomicU8>) -> std::thread::JoinHandle<()> {
-    let io_manager = IoManager::new(tx_frame, joystate);
+// Runs the emulator until the frontend stops it, handling the save state hotkeys
+fn run_emulator<T: Debugger>(emu: &mut Emulator, dbg: &mut T, state_path: &Path) {
+    while !emu.bus.io_manager.should_stop() {
+        emu.tick(dbg);
 
-    std::thread::spawn(move || {
-        let emu_res = Emulator::new(cli.rom_path, cli.boot, io_manager);
+        let res = match emu.bus.io_manager.take_state_request() {
+            Some(StateRequest::Save) => emu.save_state_file(state_path).map(|_| "Saved"),
+            Some(StateRequest::Load) => emu.load_state_file(state_path).map(|_| "Loaded"),
+            None => continue,
+        };
+        match res {
+            Ok(action) => println!("{action} state {}", state_path.display()),
+            Err(e) => println!("Error with the save state: {e}"),
+        }
+    }
+    emu.save();
+}
+
+fn launch_worker(cli: Cli, tx_frame: Sender<Frame>, tx_audio: Sender<AudioChunk>, joystate: Arc<AtomicU8>, rumble: Arc<AtomicBool>) -> (std::thread::JoinHandle<()>, Arc<AtomicBool>, Arc<AtomicU8>) {
+    let mut io_manager = IoManager::new(tx_frame, joystate, rumble, true);
+    io_manager.tx_audio = Some(tx_audio);
+    let stop = io_manager.stop_handle();
+    let state_request = io_manager.state_request_handle();
+    // Save states are written next to the ROM
+    let state_path = Path::new(&cli.rom_path).with_extension("state");
+
+    let worker = std::thread::spawn(move || {
+        let emu_res = Emulator::new(cli.rom_path, cli.boot, io_manager, cli.model);
         if let Err(e) = emu_res {
             println!("Error while creating the emulator: {e}");
             return;
@@ -116,31 +212,75 @@ fn launch_worker(cli: Cli, tx_frame: Sender<Frame>, joystate: Arc<AtomicU8>) ->
                 return;
             }
             DebugMode::None => {
-                let mut dbg = DummyDebugger::default();
-                loop {
-                    emu.tick(&mut dbg);
-                }
+                run_emulator(&mut emu, &mut DummyDebugger::default(), &state_path);
             }
             DebugMode::Log => {
                 println!("Starting emulator in log mode");
                 env_logger::init();
-                let mut dbg = LogDebugger::default();
-                loop {
-                    emu.tick(&mut dbg);
-                }
+                run_emulator(&mut emu, &mut LogDebugger::default(), &state_path);
             }
         }
-    })
+    });
+    (worker, stop, state_request)
+}
+
+fn headless_main(cli: Cli) -> std::process::ExitCode {
+    let config = HeadlessConfig {
+        frames: cli.frames,
+        dump_frames: cli.dump_frames,
+        output: cli.output,
+        until_ld_b_b: cli.until_ld_b_b,
+        until_serial: cli.until_serial,
+        model: cli.model,
+    };
+
+    match run_headless(cli.rom_path, cli.boot, &config) {
+        Ok(true) => std::process::ExitCode::SUCCESS,
+        Ok(false) => std::process::ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error while running headless: {e}");
+            std::process::ExitCode::FAILURE
+        }
+    }
 }
 
-fn main() {
+fn main() -> std::process::ExitCode {
     let cli = Cli::parse();
     set_settings(&cli);
+
+    if cli.headless {
+        return headless_main(cli);
+    }
     
     let (tx_frame, rx_frame) : (Sender<Frame>, Receiver<Frame>) = bounded(2);
+    let (tx_audio, rx_audio) : (Sender<AudioChunk>, Receiver<AudioChunk>) = bounded(32);
     let joystate = Arc::new(AtomicU8::new(0));
+    let rumble = Arc::new(AtomicBool::new(false));
     
-    let worker = launch_worker(cli, tx_frame, joystate.clone());
-    
+    let debug = cli.debug;
+    let scale = cli.scale;
+    let bindings = match cli.keys.as_deref().map(KeyBindings::parse).unwrap_or_else(|| Ok(KeyBindings::default())) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("Error while parsing key bindings: {e}");
+            return std::process::ExitCode::FAILURE;
+        }
+    };
+    let title = match std::fs::read(&cli.rom_path).map(|rom| CartridgeHeader::parse(&rom)) {
+        Ok(Ok(header)) => format!("OxideGB - {}", header.title),
+        _ => "OxideGB".to_string(),
+    };
+    let (worker, stop, state_request) = launch_worker(cli, tx_frame, tx_audio, joystate.clone(), rumble.clone());
+
+    let handles = InputHandles { joystate, rumble, state_request };
+    if let Err(e) = gui::start_gui(rx_frame, rx_audio, handles, scale, bindings, &title) {
+        println!("Error while running the GUI: {e}");
+        stop.store(true, Ordering::Relaxed);
+    } else if debug != DebugMode::Full {
+        // The TUI exits on its own, and restores the terminal before exiting
+        stop.store(true, Ordering::Relaxed);
+    }
+    // Wait for the save file to be written
     let _ = worker.join();
+    std::process::ExitCode::SUCCESS
 }
@@ -1,4 +1,6 @@
+use crate::emulator::ppu::PpuBackend;
 use once_cell::sync::OnceCell;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub static GLOB_SETTINGS : OnceCell<Arc<Settings>> = OnceCell::new();
@@ -8,4 +10,10 @@ pub struct Settings {
     pub print_serial: bool,
     pub tui_enabled: bool,
     pub doctor_logs: bool,
+    pub ly_stub: bool,
+    pub ppu_backend: PpuBackend,
+    pub sample_rate: u32,
+    pub record_audio: Option<PathBuf>,
+    pub record_channels: bool,
+    pub color_correction: bool,
 }
\ No newline at end of file